@@ -0,0 +1,570 @@
+//! Generates the morse code tables and keyboard layouts from the TOML files in
+//! `tables/` and `layouts/`, so that a different set of symbols only needs a
+//! data file to be edited. Anything that would make a table or layout
+//! ambiguous is a build error that names the file it is in.
+//!
+//! Each file in `tables/` becomes a `CodeTree` static named after the file in
+//! upper case, e.g. `tables/itu.toml` is `ITU`:
+//!
+//! ```toml
+//! description = "The doc comment for the static"
+//! # optional, another table to look up codes that aren't in this one
+//! fallback = "itu"
+//!
+//! [codes]
+//! ".-" = "a"
+//! "----" = "ch"
+//!
+//! [prosigns]
+//! AR = ".-.-."
+//! ```
+//!
+//! Each file in `layouts/` becomes a `LayoutTable` const in the same way. The
+//! `base`, `shift` and `alt_gr` levels list the characters on each key in
+//! [KEY_ROWS], with a space for a key that doesn't type anything at that level
+//! or is a dead key. `alt_gr` can be left out if the layout doesn't use it.
+//!
+//! ```toml
+//! base = ["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;' ", " zxcvbnm,./"]
+//! shift = ["~!@#$%^&*()_+", "QWERTYUIOP{}|", "ASDFGHJKL:\" ", " ZXCVBNM<>?"]
+//!
+//! [[dead_keys]]
+//! level = "shift"
+//! keycode = 0x2E
+//! accent = "grave"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+/// The longest code that can be decoded, which must match `MAX_CODE_LEN` in
+/// `src/code_table.rs`
+const MAX_CODE_LEN: usize = 10;
+
+/// The names of the prosigns a table can use, which must match the `Prosign`
+/// variants in `src/code_table.rs`
+const PROSIGNS: [&str; 8] = ["AR", "SK", "BT", "KN", "SN", "HH", "CT", "DO"];
+
+/// The HID usage IDs of the keys described by each layout, row by row from the
+/// number row down. This includes the keys that are only on ANSI keyboards
+/// (`0x31` above Enter) and only on ISO keyboards (`0x32` next to Enter and
+/// `0x64` next to the left shift).
+const KEY_ROWS: [&[u8]; 4] = [
+    &[
+        0x35, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x2D, 0x2E,
+    ],
+    &[
+        0x14, 0x1A, 0x08, 0x15, 0x17, 0x1C, 0x18, 0x0C, 0x12, 0x13, 0x2F, 0x30, 0x31,
+    ],
+    &[
+        0x04, 0x16, 0x07, 0x09, 0x0A, 0x0B, 0x0D, 0x0E, 0x0F, 0x33, 0x34, 0x32,
+    ],
+    &[
+        0x64, 0x1D, 0x1B, 0x06, 0x19, 0x05, 0x11, 0x10, 0x36, 0x37, 0x38,
+    ],
+];
+
+/// The levels of a layout and the modifiers used to reach them
+const LEVELS: [(&str, &str); 3] = [("base", "0"), ("shift", "SHIFT"), ("alt_gr", "ALT_GR")];
+
+/// The accents that dead keys can add, and their `Accent` variants
+const ACCENTS: [(&str, &str); 5] = [
+    ("acute", "Acute"),
+    ("grave", "Grave"),
+    ("circumflex", "Circumflex"),
+    ("diaeresis", "Diaeresis"),
+    ("tilde", "Tilde"),
+];
+
+fn main() {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let tables = toml_files("tables")
+        .iter()
+        .map(|path| CodeTableFile::read(path))
+        .collect::<Vec<_>>();
+    check_fallbacks(&tables);
+    let code = tables
+        .iter()
+        .map(CodeTableFile::generate)
+        .collect::<String>();
+    fs::write(out.join("code_tables.rs"), code).unwrap();
+
+    let mut code = generate_keycodes();
+    for path in toml_files("layouts") {
+        code += &LayoutFile::read(&path).generate();
+    }
+    fs::write(out.join("layouts.rs"), code).unwrap();
+
+    println!("cargo:rerun-if-changed=tables");
+    println!("cargo:rerun-if-changed=layouts");
+}
+
+/// Every TOML file in a directory, sorted so the output is the same each time
+fn toml_files(dir: &str) -> Vec<PathBuf> {
+    let mut paths = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("unable to read {dir}: {e}"))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Stops the build with an error in the given file
+fn fail(path: &Path, message: impl std::fmt::Display) -> ! {
+    panic!("{}: {message}", path.display())
+}
+
+/// Reads a TOML file, only allowing the given keys at the top level
+fn read_toml(path: &Path, allowed: &[&str]) -> Table {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let contents = fs::read_to_string(path).unwrap_or_else(|e| fail(path, e));
+    let table = contents.parse::<Table>().unwrap_or_else(|e| fail(path, e));
+    if let Some(key) = table.keys().find(|key| !allowed.contains(&key.as_str())) {
+        fail(path, format!("unknown key `{key}`"));
+    }
+    table
+}
+
+/// The name of the generated item for a file, e.g. `ITU` for `tables/itu.toml`
+fn item_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    if !stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        fail(path, "file names may only use letters, digits and `_`");
+    }
+    stem.to_uppercase()
+}
+
+fn as_str<'a>(path: &Path, what: &str, value: &'a Value) -> &'a str {
+    value
+        .as_str()
+        .unwrap_or_else(|| fail(path, format!("{what} should be a string")))
+}
+
+fn as_table<'a>(path: &Path, what: &str, value: &'a Value) -> &'a Table {
+    value
+        .as_table()
+        .unwrap_or_else(|| fail(path, format!("{what} should be a table")))
+}
+
+/// What a code types
+enum Output {
+    Char(char),
+    Text(String),
+    Prosign(String),
+}
+
+impl Output {
+    fn generate(&self) -> String {
+        match self {
+            Output::Char(c) => format!("Decoded::Char({c:?})"),
+            Output::Text(text) => format!("Decoded::Text({text:?})"),
+            Output::Prosign(name) => format!("Decoded::Prosign(Prosign::{name})"),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Output::Char(c) => format!("{c:?}"),
+            Output::Text(text) => format!("{text:?}"),
+            Output::Prosign(name) => format!("<{name}>"),
+        }
+    }
+}
+
+/// A morse code table read from `tables/`
+struct CodeTableFile {
+    path: PathBuf,
+    name: String,
+    description: String,
+    fallback: Option<String>,
+    entries: Vec<(String, Output)>,
+}
+
+impl CodeTableFile {
+    fn read(path: &Path) -> Self {
+        let toml = read_toml(path, &["description", "fallback", "codes", "prosigns"]);
+        let description = toml
+            .get("description")
+            .map(|value| as_str(path, "`description`", value).to_string())
+            .unwrap_or_else(|| fail(path, "missing `description`"));
+        let fallback = toml
+            .get("fallback")
+            .map(|value| as_str(path, "`fallback`", value).to_uppercase());
+
+        let mut entries = Vec::new();
+        let codes = toml
+            .get("codes")
+            .map(|value| as_table(path, "`codes`", value))
+            .unwrap_or_else(|| fail(path, "missing `codes`"));
+        for (code, value) in codes {
+            let text = as_str(path, &format!("the value for {code:?}"), value);
+            let mut chars = text.chars();
+            let output = match (chars.next(), chars.next()) {
+                (None, _) => fail(path, format!("{code:?} doesn't type anything")),
+                (Some(c), None) => Output::Char(c),
+                _ => Output::Text(text.to_string()),
+            };
+            entries.push((code.clone(), output));
+        }
+        if let Some(prosigns) = toml.get("prosigns") {
+            for (name, code) in as_table(path, "`prosigns`", prosigns) {
+                if !PROSIGNS.contains(&name.as_str()) {
+                    fail(path, format!("{name} = {code} isn't a known prosign"));
+                }
+                let code = as_str(path, &format!("the code for {name}"), code);
+                entries.push((code.to_string(), Output::Prosign(name.clone())));
+            }
+        }
+
+        let table = Self {
+            path: path.to_path_buf(),
+            name: item_name(path),
+            description,
+            fallback,
+            entries,
+        };
+        table.check();
+        table
+    }
+
+    /// Checks that every code can be decoded and that each code and output is
+    /// only used once, so decoding and encoding are never ambiguous
+    fn check(&self) {
+        let mut codes = BTreeMap::new();
+        let mut outputs = BTreeMap::new();
+        for (code, output) in &self.entries {
+            check_code(&self.path, code);
+            if let Some(other) = codes.insert(code, output) {
+                fail(
+                    &self.path,
+                    format!(
+                        "{code:?} is used for both {} and {}",
+                        other.describe(),
+                        output.describe()
+                    ),
+                );
+            }
+            if let Some(other) = outputs.insert(output.describe(), code) {
+                fail(
+                    &self.path,
+                    format!(
+                        "{} has two codes, {other:?} and {code:?}",
+                        output.describe()
+                    ),
+                );
+            }
+        }
+    }
+
+    fn generate(&self) -> String {
+        let name = &self.name;
+        let mut code = format!("const {name}_TABLE: &[(&str, Decoded)] = &[\n");
+        for (morse, output) in &self.entries {
+            writeln!(code, "    ({morse:?}, {}),", output.generate()).unwrap();
+        }
+        code += "];\n\n";
+
+        writeln!(
+            code,
+            "const {name}_NODE_COUNT: usize = node_count({name}_TABLE);"
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "static {name}_NODES: [Node; {name}_NODE_COUNT] = build_nodes({name}_TABLE);\n"
+        )
+        .unwrap();
+        writeln!(code, "/// {}", self.description).unwrap();
+        match &self.fallback {
+            Some(fallback) => writeln!(
+                code,
+                "pub static {name}: CodeTree = CodeTree::with_fallback(&{name}_NODES, &{fallback});\n"
+            ),
+            None => writeln!(
+                code,
+                "pub static {name}: CodeTree = CodeTree::new(&{name}_NODES);\n"
+            ),
+        }
+        .unwrap();
+        code
+    }
+}
+
+/// Checks that a code only has elements the decoder knows about, and that it
+/// is short enough to decode. Spaces only come between elements, so a code
+/// that starts or ends with one, or has two in a row, could never be sent.
+fn check_code(path: &Path, code: &str) {
+    if code.is_empty() || code.len() > MAX_CODE_LEN {
+        fail(
+            path,
+            format!("{code:?} should have between 1 and {MAX_CODE_LEN} elements"),
+        );
+    }
+    if let Some(c) = code.chars().find(|c| !".-_= ".contains(*c)) {
+        fail(
+            path,
+            format!("{code:?} has {c:?}, codes can only use '.', '-', '_', '=' and ' '"),
+        );
+    }
+    if code.starts_with(' ') || code.ends_with(' ') || code.contains("  ") {
+        fail(
+            path,
+            format!("{code:?} has a space that isn't between two elements"),
+        );
+    }
+}
+
+/// Checks that every fallback is another table, and that following them never
+/// goes round in a loop
+fn check_fallbacks(tables: &[CodeTableFile]) {
+    let fallback_of = |name: &str| {
+        tables
+            .iter()
+            .find(|table| table.name == name)
+            .and_then(|table| table.fallback.as_deref())
+    };
+
+    for table in tables {
+        let mut seen = vec![table.name.as_str()];
+        let mut next = table.fallback.as_deref();
+        while let Some(fallback) = next {
+            if !tables.iter().any(|table| table.name == fallback) {
+                fail(
+                    &table.path,
+                    format!(
+                        "there is no {} table to fall back to",
+                        fallback.to_lowercase()
+                    ),
+                );
+            }
+            if seen.contains(&fallback) {
+                fail(&table.path, "the fallback tables go round in a loop");
+            }
+            seen.push(fallback);
+            next = fallback_of(fallback);
+        }
+    }
+}
+
+/// A keyboard layout read from `layouts/`
+struct LayoutFile {
+    path: PathBuf,
+    name: String,
+    /// The rows of each level in [LEVELS], or `None` for a level that isn't
+    /// used
+    levels: Vec<Option<Vec<String>>>,
+    /// The modifier, key code and accent of each dead key
+    dead_keys: Vec<(&'static str, u8, &'static str)>,
+}
+
+impl LayoutFile {
+    fn read(path: &Path) -> Self {
+        let toml = read_toml(path, &["base", "shift", "alt_gr", "dead_keys"]);
+
+        let mut levels = Vec::new();
+        for (level, _) in LEVELS {
+            let Some(rows) = toml.get(level) else {
+                if level != "alt_gr" {
+                    fail(path, format!("missing `{level}`"));
+                }
+                levels.push(None);
+                continue;
+            };
+            let rows = rows
+                .as_array()
+                .filter(|rows| rows.len() == KEY_ROWS.len())
+                .unwrap_or_else(|| {
+                    fail(
+                        path,
+                        format!("`{level}` should be a list of {} rows", KEY_ROWS.len()),
+                    )
+                })
+                .iter()
+                .map(|row| as_str(path, &format!("each row of `{level}`"), row).to_string())
+                .collect::<Vec<_>>();
+            for (number, (row, keys)) in rows.iter().zip(KEY_ROWS).enumerate() {
+                let count = row.chars().count();
+                if count != keys.len() {
+                    fail(
+                        path,
+                        format!(
+                            "row {} of `{level}` has {count} keys but should have {}",
+                            number + 1,
+                            keys.len()
+                        ),
+                    );
+                }
+            }
+            levels.push(Some(rows));
+        }
+
+        let mut dead_keys = Vec::new();
+        if let Some(keys) = toml.get("dead_keys") {
+            let keys = keys
+                .as_array()
+                .unwrap_or_else(|| fail(path, "`dead_keys` should be a list"));
+            for key in keys {
+                dead_keys.push(read_dead_key(path, as_table(path, "each dead key", key)));
+            }
+        }
+
+        let layout = Self {
+            path: path.to_path_buf(),
+            name: item_name(path),
+            levels,
+            dead_keys,
+        };
+        layout.check();
+        layout
+    }
+
+    /// The character on each key at each level, with the key's modifier and
+    /// key code
+    fn keys(&self) -> impl Iterator<Item = (char, &'static str, u8)> + '_ {
+        self.levels
+            .iter()
+            .zip(LEVELS)
+            .filter_map(|(rows, (_, modifier))| Some((rows.as_ref()?, modifier)))
+            .flat_map(|(rows, modifier)| {
+                rows.iter()
+                    .flat_map(|row| row.chars())
+                    .zip(KEY_ROWS.iter().flat_map(|keys| keys.iter().copied()))
+                    .map(move |(c, keycode)| (c, modifier, keycode))
+            })
+    }
+
+    /// Checks that each character and accent can only be typed one way, and
+    /// that dead keys don't also type a character
+    fn check(&self) {
+        let mut chars = BTreeMap::new();
+        for (c, modifier, keycode) in self.keys().filter(|&(c, _, _)| c != ' ') {
+            if let Some((other, other_keycode)) = chars.insert(c, (modifier, keycode)) {
+                fail(
+                    &self.path,
+                    format!(
+                        "{c:?} is on both {} and {}",
+                        describe_key(other, other_keycode),
+                        describe_key(modifier, keycode)
+                    ),
+                );
+            }
+        }
+
+        for (i, &(modifier, keycode, accent)) in self.dead_keys.iter().enumerate() {
+            if let Some((c, _, _)) = self
+                .keys()
+                .find(|&(c, m, k)| c != ' ' && m == modifier && k == keycode)
+            {
+                fail(
+                    &self.path,
+                    format!(
+                        "the {accent} dead key on {} also types {c:?}",
+                        describe_key(modifier, keycode)
+                    ),
+                );
+            }
+            if let Some(&(_, _, other)) = self.dead_keys[..i]
+                .iter()
+                .find(|&&(m, k, a)| a == accent || (m == modifier && k == keycode))
+            {
+                fail(
+                    &self.path,
+                    format!(
+                        "the {accent} dead key on {} clashes with the {other} dead key",
+                        describe_key(modifier, keycode)
+                    ),
+                );
+            }
+        }
+    }
+
+    fn generate(&self) -> String {
+        let mut code = format!("const {}: LayoutTable = LayoutTable {{\n", self.name);
+        for (rows, (level, _)) in self.levels.iter().zip(LEVELS) {
+            match rows {
+                Some(rows) => {
+                    writeln!(code, "    {level}: [").unwrap();
+                    for row in rows {
+                        writeln!(code, "        {row:?},").unwrap();
+                    }
+                    code += "    ],\n";
+                }
+                None => writeln!(code, "    {level}: EMPTY,").unwrap(),
+            }
+        }
+        code += "    dead_keys: &[\n";
+        for (modifier, keycode, accent) in &self.dead_keys {
+            let variant = ACCENTS
+                .iter()
+                .find(|&&(name, _)| name == *accent)
+                .map(|&(_, variant)| variant)
+                .unwrap();
+            writeln!(
+                code,
+                "        (KeyStroke::new({modifier}, {keycode:#04X}), Accent::{variant}),"
+            )
+            .unwrap();
+        }
+        code += "    ],\n};\n\n";
+        code
+    }
+}
+
+/// Reads the level, key code and accent of a dead key
+fn read_dead_key(path: &Path, key: &Table) -> (&'static str, u8, &'static str) {
+    let field = |name: &str| {
+        key.get(name)
+            .unwrap_or_else(|| fail(path, format!("a dead key is missing `{name}`")))
+    };
+
+    let level = as_str(path, "`level`", field("level"));
+    let modifier = LEVELS
+        .iter()
+        .find(|&&(name, _)| name == level)
+        .map(|&(_, modifier)| modifier)
+        .unwrap_or_else(|| fail(path, format!("unknown level `{level}`")));
+
+    let keycode = field("keycode")
+        .as_integer()
+        .and_then(|keycode| u8::try_from(keycode).ok())
+        .filter(|keycode| KEY_ROWS.iter().any(|keys| keys.contains(keycode)))
+        .unwrap_or_else(|| fail(path, "a dead key's `keycode` isn't a key in the layout"));
+
+    let accent = as_str(path, "`accent`", field("accent"));
+    let accent = ACCENTS
+        .iter()
+        .find(|&&(name, _)| name == accent)
+        .map(|&(name, _)| name)
+        .unwrap_or_else(|| fail(path, format!("unknown accent `{accent}`")));
+
+    (modifier, keycode, accent)
+}
+
+/// Describes a key for an error message, e.g. `SHIFT+0x2E`
+fn describe_key(modifier: &str, keycode: u8) -> String {
+    match modifier {
+        "0" => format!("{keycode:#04X}"),
+        _ => format!("{modifier}+{keycode:#04X}"),
+    }
+}
+
+/// The key codes that the rows of every layout describe
+fn generate_keycodes() -> String {
+    let keycodes = KEY_ROWS
+        .iter()
+        .flat_map(|keys| keys.iter())
+        .map(|keycode| format!("{keycode:#04X}"))
+        .collect::<Vec<_>>();
+    format!(
+        "const KEY_COUNT: usize = {};\n\nconst KEYCODES: [u8; KEY_COUNT] = [{}];\n\n",
+        keycodes.len(),
+        keycodes.join(", ")
+    )
+}
@@ -0,0 +1,91 @@
+//! A fixed size buffer between the tasks that read the buttons and the task
+//! that types on the host. Adding to it never waits, so a slow or stalled host
+//! can't hold up reading the buttons or decoding, and when it fills up events
+//! are dropped according to an [OverflowPolicy] and counted.
+
+/// Which events are dropped when an [EventBuffer] is full
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OverflowPolicy {
+    /// Keeps what is already in the buffer and drops the new event, so what is
+    /// typed is the start of what was sent
+    #[default]
+    DropNewest,
+    /// Makes room for the new event by dropping the oldest one, so what is
+    /// typed is the end of what was sent
+    DropOldest,
+}
+
+/// A first in, first out buffer of up to `N` events
+#[derive(Clone, Debug)]
+pub struct EventBuffer<T: Copy, const N: usize> {
+    events: [Option<T>; N],
+    start: usize,
+    len: usize,
+    /// What to do when the buffer is full
+    pub policy: OverflowPolicy,
+    overflows: u32,
+}
+
+impl<T: Copy, const N: usize> Default for EventBuffer<T, N> {
+    fn default() -> Self {
+        Self::new(OverflowPolicy::default())
+    }
+}
+
+impl<T: Copy, const N: usize> EventBuffer<T, N> {
+    pub const fn new(policy: OverflowPolicy) -> Self {
+        Self {
+            events: [None; N],
+            start: 0,
+            len: 0,
+            policy,
+            overflows: 0,
+        }
+    }
+
+    /// The number of events waiting
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of events that have been dropped because the buffer was
+    /// full. This wraps around rather than overflowing itself.
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Adds an event to the end of the buffer, returning `false` if an event
+    /// had to be dropped to do so
+    pub fn push(&mut self, event: T) -> bool {
+        if self.len == N {
+            self.overflows = self.overflows.wrapping_add(1);
+            match self.policy {
+                OverflowPolicy::DropNewest => return false,
+                OverflowPolicy::DropOldest => {
+                    self.pop();
+                    self.push(event);
+                    return false;
+                }
+            }
+        }
+        self.events[(self.start + self.len) % N] = Some(event);
+        self.len += 1;
+        true
+    }
+
+    /// Takes the oldest event from the buffer
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.start].take();
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        event
+    }
+}
@@ -0,0 +1,470 @@
+//! Morse code tables. Each table is stored as a tree that is built at compile
+//! time, where every node has a child for a dit and a child for a dah. Looking
+//! up a sequence walks one node per element, so costs O(length). The tables
+//! themselves are in the TOML files in `tables/`, which build.rs checks and
+//! turns into Rust.
+
+/// The longest sequence of elements that can be decoded
+pub const MAX_CODE_LEN: usize = 10;
+
+/// A single element of a morse sequence
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Element {
+    Dit,
+    Dah,
+    /// The long dash used for `L` in American Morse, written as `_`
+    LongDah,
+    /// The extra long dash used for `0` in American Morse, written as `=`
+    ExtraLongDah,
+    /// The space inside some American Morse characters, e.g. `C` (`.. .`)
+    Space,
+}
+
+/// The number of different elements, i.e. the number of children for each
+/// node in a [CodeTree]
+const ELEMENT_COUNT: usize = 5;
+
+impl Element {
+    /// Every element, in the same order as their discriminants
+    pub const ALL: [Element; ELEMENT_COUNT] = [
+        Element::Dit,
+        Element::Dah,
+        Element::LongDah,
+        Element::ExtraLongDah,
+        Element::Space,
+    ];
+
+    const fn from_ascii(c: u8) -> Self {
+        match Self::try_from_ascii(c) {
+            Some(element) => element,
+            None => panic!("morse codes may only contain '.', '-', '_', '=' and ' '"),
+        }
+    }
+
+    /// The element written as the given character, see [Element::as_char]
+    pub(crate) const fn try_from_ascii(c: u8) -> Option<Self> {
+        match c {
+            b'.' => Some(Element::Dit),
+            b'-' => Some(Element::Dah),
+            b'_' => Some(Element::LongDah),
+            b'=' => Some(Element::ExtraLongDah),
+            b' ' => Some(Element::Space),
+            _ => None,
+        }
+    }
+
+    /// The standard length of the element in dits. For a [Element::Space] this
+    /// is the length of the gap, which replaces the usual one dit gap between
+    /// elements.
+    pub fn units(self) -> u64 {
+        match self {
+            Element::Dit => 1,
+            Element::Dah => 3,
+            Element::LongDah => 6,
+            Element::ExtraLongDah => 9,
+            Element::Space => 2,
+        }
+    }
+
+    /// The character used to write the element, e.g. `.` for a dit
+    pub fn as_char(self) -> char {
+        match self {
+            Element::Dit => '.',
+            Element::Dah => '-',
+            Element::LongDah => '_',
+            Element::ExtraLongDah => '=',
+            Element::Space => ' ',
+        }
+    }
+}
+
+/// A sequence of elements, up to [MAX_CODE_LEN] elements long
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MorseCode {
+    elements: [Element; MAX_CODE_LEN],
+    len: usize,
+}
+
+impl Default for MorseCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MorseCode {
+    pub const fn new() -> Self {
+        Self {
+            elements: [Element::Dit; MAX_CODE_LEN],
+            len: 0,
+        }
+    }
+
+    /// Parses a code written as a string of `.` and `-` characters, e.g. `".-"`.
+    /// American Morse codes can also use `_`, `=` and ` `, see [Element].
+    pub const fn from_str(code: &str) -> Self {
+        let bytes = code.as_bytes();
+        if bytes.len() > MAX_CODE_LEN {
+            panic!("morse code is too long");
+        }
+
+        let mut result = Self::new();
+        while result.len < bytes.len() {
+            result.elements[result.len] = Element::from_ascii(bytes[result.len]);
+            result.len += 1;
+        }
+        result
+    }
+
+    /// Adds an element to the end of the code. Returns `false` if the code is
+    /// already [MAX_CODE_LEN] elements long, in which case it is unchanged.
+    pub fn push(&mut self, element: Element) -> bool {
+        if self.len == MAX_CODE_LEN {
+            return false;
+        }
+
+        self.elements[self.len] = element;
+        self.len += 1;
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn elements(&self) -> &[Element] {
+        &self.elements[..self.len]
+    }
+}
+
+/// The standard procedural signals, which are sent as run-together characters
+/// without a letter gap between them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Prosign {
+    /// `.-.-.` - end of message
+    AR,
+    /// `...-.-` - end of contact
+    SK,
+    /// `-...-` - break, new paragraph
+    BT,
+    /// `-.--.` - go ahead, named station only
+    KN,
+    /// `...-.` - understood
+    SN,
+    /// `........` - error
+    HH,
+    /// `-.-.-` - start of transmission
+    CT,
+    /// `-..---` - switch to Japanese [Wabun](CodeTable::Wabun) code, until
+    /// [Prosign::SN] switches back
+    DO,
+}
+
+impl Prosign {
+    /// The number of prosigns the decoder knows about. build.rs has a list of
+    /// their names too, to check the tables with.
+    pub const COUNT: usize = 8;
+
+    /// Every prosign, in the same order as their discriminants
+    pub const ALL: [Prosign; Prosign::COUNT] = [
+        Prosign::AR,
+        Prosign::SK,
+        Prosign::BT,
+        Prosign::KN,
+        Prosign::SN,
+        Prosign::HH,
+        Prosign::CT,
+        Prosign::DO,
+    ];
+
+    /// The letters that make up this prosign, e.g. `"AR"`
+    pub fn name(self) -> &'static str {
+        match self {
+            Prosign::AR => "AR",
+            Prosign::SK => "SK",
+            Prosign::BT => "BT",
+            Prosign::KN => "KN",
+            Prosign::SN => "SN",
+            Prosign::HH => "HH",
+            Prosign::CT => "CT",
+            Prosign::DO => "DO",
+        }
+    }
+
+    /// Finds the prosign with the given name, ignoring case
+    pub fn from_name(name: &str) -> Option<Prosign> {
+        Prosign::ALL
+            .into_iter()
+            .find(|prosign| prosign.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the printable character that shares this prosign's code, if any
+    pub fn as_char(self) -> Option<char> {
+        match self {
+            Prosign::AR => Some('+'),
+            Prosign::BT => Some('='),
+            Prosign::KN => Some('('),
+            _ => None,
+        }
+    }
+}
+
+/// A value that has been decoded from a complete morse sequence
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Decoded {
+    Char(char),
+    /// More than one character sent as a single code, e.g. `ch`
+    Text(&'static str),
+    Prosign(Prosign),
+}
+
+/// A node in a [CodeTree]. A child index of `0` means there is no child, as
+/// the root node can never be a child.
+#[derive(Clone, Copy, Debug)]
+pub struct Node {
+    children: [u16; ELEMENT_COUNT],
+    value: Option<Decoded>,
+}
+
+impl Node {
+    const EMPTY: Node = Node {
+        children: [0; ELEMENT_COUNT],
+        value: None,
+    };
+}
+
+/// The maximum number of nodes a single table can build into
+const MAX_NODES: usize = 1024;
+
+/// Builds the nodes for a table, returning the nodes and how many were used.
+/// Panics (at compile time when used in a `const`) if two entries share a code.
+const fn build<const N: usize>(table: &[(&str, Decoded)]) -> ([Node; N], usize) {
+    let mut nodes = [Node::EMPTY; N];
+    let mut used = 1;
+
+    let mut entry = 0;
+    while entry < table.len() {
+        let (code, value) = table[entry];
+        let code = MorseCode::from_str(code);
+
+        let mut node = 0;
+        let mut idx = 0;
+        while idx < code.len {
+            let child = code.elements[idx] as usize;
+            if nodes[node].children[child] == 0 {
+                if used == N {
+                    panic!("too many nodes in morse code table");
+                }
+                nodes[node].children[child] = used as u16;
+                used += 1;
+            }
+            node = nodes[node].children[child] as usize;
+            idx += 1;
+        }
+
+        if nodes[node].value.is_some() {
+            panic!("duplicate code in morse code table");
+        }
+        nodes[node].value = Some(value);
+        entry += 1;
+    }
+
+    (nodes, used)
+}
+
+/// Counts the number of nodes required to build a tree for the given table
+pub const fn node_count(table: &[(&str, Decoded)]) -> usize {
+    build::<MAX_NODES>(table).1
+}
+
+/// Builds the nodes of a tree for the given table. `N` must be the value
+/// returned by [node_count].
+pub const fn build_nodes<const N: usize>(table: &[(&str, Decoded)]) -> [Node; N] {
+    build::<N>(table).0
+}
+
+/// A tree of morse codes that can be searched one element at a time. A tree
+/// can fall back to another one for codes that it doesn't have, so national
+/// tables only need to list the codes that differ from the international one.
+pub struct CodeTree {
+    nodes: &'static [Node],
+    fallback: Option<&'static CodeTree>,
+}
+
+impl CodeTree {
+    pub const fn new(nodes: &'static [Node]) -> Self {
+        Self {
+            nodes,
+            fallback: None,
+        }
+    }
+
+    /// Creates a tree that looks up any code that isn't in `nodes` in
+    /// `fallback` instead
+    pub const fn with_fallback(nodes: &'static [Node], fallback: &'static CodeTree) -> Self {
+        Self {
+            nodes,
+            fallback: Some(fallback),
+        }
+    }
+
+    /// Finds the value for the given code, returning `None` if the code isn't
+    /// in the table
+    pub fn lookup(&self, code: &MorseCode) -> Option<Decoded> {
+        self.lookup_own(code)
+            .or_else(|| self.fallback?.lookup(code))
+    }
+
+    /// Finds the code for the given value, returning `None` if the value isn't
+    /// in the table. This searches the whole tree, so is slower than [lookup].
+    ///
+    /// [lookup]: CodeTree::lookup
+    pub fn encode(&self, value: Decoded) -> Option<MorseCode> {
+        self.find(0, MorseCode::new(), value).or_else(|| {
+            self.fallback?
+                .encode(value)
+                .filter(|code| self.lookup_own(code).is_none())
+        })
+    }
+
+    /// Calls `f` with every code in the table and its value, including any
+    /// from the fallback table that aren't replaced by this one
+    pub fn for_each_entry(&self, mut f: impl FnMut(&MorseCode, Decoded)) {
+        self.visit_all(&mut f);
+    }
+
+    /// Calls `f` with every entry, as [for_each_entry]. This takes a trait
+    /// object so it can recurse through the fallback tables.
+    ///
+    /// [for_each_entry]: CodeTree::for_each_entry
+    fn visit_all(&self, f: &mut dyn FnMut(&MorseCode, Decoded)) {
+        self.visit(0, MorseCode::new(), f);
+        if let Some(fallback) = self.fallback {
+            fallback.visit_all(&mut |code, value| {
+                if self.lookup_own(code).is_none() {
+                    f(code, value);
+                }
+            });
+        }
+    }
+
+    /// Finds the value for the given code without using the fallback table
+    fn lookup_own(&self, code: &MorseCode) -> Option<Decoded> {
+        let mut node = 0;
+        for element in code.elements() {
+            node = match self.nodes[node].children[*element as usize] {
+                0 => return None,
+                child => child as usize,
+            };
+        }
+
+        self.nodes[node].value
+    }
+
+    /// Calls `f` with every entry in the subtree below `node`, which is reached
+    /// by `code`
+    fn visit(&self, node: usize, code: MorseCode, f: &mut dyn FnMut(&MorseCode, Decoded)) {
+        if let Some(value) = self.nodes[node].value {
+            f(&code, value);
+        }
+
+        for element in Element::ALL {
+            let child = self.nodes[node].children[element as usize];
+            if child != 0 {
+                let mut code = code;
+                code.push(element);
+                self.visit(child as usize, code, f);
+            }
+        }
+    }
+
+    /// Searches the subtree below `node`, which is reached by `code`
+    fn find(&self, node: usize, code: MorseCode, value: Decoded) -> Option<MorseCode> {
+        if self.nodes[node].value == Some(value) {
+            return Some(code);
+        }
+
+        Element::ALL.into_iter().find_map(|element| {
+            match self.nodes[node].children[element as usize] {
+                0 => None,
+                child => {
+                    let mut code = code;
+                    code.push(element);
+                    self.find(child as usize, code, value)
+                }
+            }
+        })
+    }
+}
+
+// The tables are generated by build.rs from the files in `tables/`, e.g. `ITU`
+// from `tables/itu.toml`
+include!(concat!(env!("OUT_DIR"), "/code_tables.rs"));
+
+/// The code tables that the decoder can switch between
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CodeTable {
+    /// ITU international morse code
+    #[default]
+    International,
+    /// International morse code with the extra Latin letters, e.g. `ä` and `ñ`
+    Latin,
+    /// Russian Cyrillic
+    Cyrillic,
+    /// Greek
+    Greek,
+    /// Japanese Wabun, which is also switched to by [Prosign::DO]
+    Wabun,
+    /// Hebrew
+    Hebrew,
+    /// Arabic
+    Arabic,
+    /// Korean SKATS
+    Korean,
+    /// American (railroad) Morse, which the decoder reads with long dashes and
+    /// spaces inside characters
+    American,
+}
+
+impl CodeTable {
+    /// Every code table
+    pub const ALL: [CodeTable; 9] = [
+        CodeTable::International,
+        CodeTable::Latin,
+        CodeTable::Cyrillic,
+        CodeTable::Greek,
+        CodeTable::Wabun,
+        CodeTable::Hebrew,
+        CodeTable::Arabic,
+        CodeTable::Korean,
+        CodeTable::American,
+    ];
+
+    /// The tree used to look up codes in this table
+    pub fn tree(self) -> &'static CodeTree {
+        match self {
+            CodeTable::International => &ITU,
+            CodeTable::Latin => &LATIN,
+            CodeTable::Cyrillic => &CYRILLIC,
+            CodeTable::Greek => &GREEK,
+            CodeTable::Wabun => &WABUN,
+            CodeTable::Hebrew => &HEBREW,
+            CodeTable::Arabic => &ARABIC,
+            CodeTable::Korean => &KOREAN,
+            CodeTable::American => &AMERICAN,
+        }
+    }
+
+    /// Whether the table uses the American Morse elements and spacing
+    pub fn is_american(self) -> bool {
+        self == CodeTable::American
+    }
+}
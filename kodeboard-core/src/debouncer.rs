@@ -0,0 +1,47 @@
+//! Contains code for firmware debouncing of inputs using 16 consecutive checks.
+//! The inputs are pulled up, so a low level (`0`) is considered "on".
+
+const ON: u16 = 0b1111111111111111;
+const OFF: u16 = 0;
+
+/// A debounced input that checks 16 times whether a switch is "on" before
+/// returning that it is "on", and 16 times whether it is "off" before returning
+/// that it is "off".
+#[derive(Clone, Copy)]
+pub struct DebouncedInput {
+    memory: u16,
+    previous_state: bool,
+}
+
+impl DebouncedInput {
+    pub fn new(is_on: bool) -> Self {
+        Self {
+            memory: if is_on { ON } else { OFF },
+            previous_state: is_on,
+        }
+    }
+
+    pub fn current(&self) -> bool {
+        self.previous_state
+    }
+
+    /// Debounces the given input taking the current pin level and returning
+    /// `true` if the input is on after debouncing. The inputs are pulled up, so
+    /// `is_high` should be `false` while the switch is pressed and the input is
+    /// on.
+    ///
+    /// The debounced state is remembered each time it settles, so while the
+    /// input is bouncing this returns the last state it settled in rather than
+    /// the state it was created with.
+    pub fn debounce(&mut self, is_high: bool) -> bool {
+        self.memory = (self.memory << 1) | if is_high { 0 } else { 1 };
+
+        if self.memory == ON {
+            self.previous_state = true;
+        } else if self.memory == OFF {
+            self.previous_state = false;
+        }
+
+        self.previous_state
+    }
+}
@@ -0,0 +1,667 @@
+use crate::Timestamp;
+use crate::code_table::{CodeTable, Decoded, Element, MAX_CODE_LEN, MorseCode, Prosign};
+use crate::guess;
+use crate::speed::SpeedTracker;
+use crate::user_table::UserCodes;
+
+/// The reasons a sequence of dits and dahs could not be decoded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// More than [MAX_CODE_LEN] elements were sent without a
+    /// break. Only the first elements are kept.
+    TooLong(MorseCode),
+    /// The sequence isn't in the code table
+    Unknown(MorseCode),
+}
+
+/// What the decoder does when a sequence can't be decoded. The decoder always
+/// reports a [DecoderEvent::Error], and then reports any replacement as a
+/// separate event.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorPolicy {
+    /// Don't type anything
+    #[default]
+    Ignore,
+    /// Type the given character instead, e.g. `_` or `?`, so the operator can
+    /// see where the mistake was
+    Placeholder(char),
+    /// Type the dits and dahs that were sent, e.g. `.-..-`, reported as a
+    /// [DecoderEvent::Pattern]
+    RawPattern,
+    /// Type whichever character has the closest code, allowing for key
+    /// presses that were nearly long or short enough to be the other element.
+    /// Sequences that are too long are ignored.
+    BestGuess,
+}
+
+/// Something that the decoder found in the input
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecoderEvent {
+    /// A dit or dah, along with how long the key was held down (or up, for an
+    /// American Morse [Element::Space]). Every element is reported, even if the
+    /// sequence turns out to be invalid.
+    Element { element: Element, duration_ms: u64 },
+    /// A complete character
+    Character(char),
+    /// More than one character sent as a single code, e.g. `ch`
+    Text(&'static str),
+    /// A complete prosign
+    Prosign(Prosign),
+    /// The gap at the end of a word, which is only reported once per word
+    WordGap,
+    /// A run of 6 or 7 dits. This is a shorter version of the 8 dit
+    /// [Prosign::HH] error signal, used to say that only the last character
+    /// was wrong.
+    ShortError,
+    /// A sequence that could not be decoded
+    Error(DecodeError),
+    /// The raw dits and dahs of a sequence that could not be decoded, which
+    /// should be typed as `.` and `-`. This is only reported when using
+    /// [ErrorPolicy::RawPattern].
+    Pattern(MorseCode),
+    /// A sequence from the operator's own code table, which is looked up in
+    /// the [UserTable](crate::user_table::UserTable) by whoever has it
+    UserCode(MorseCode),
+}
+
+impl From<Decoded> for DecoderEvent {
+    fn from(decoded: Decoded) -> Self {
+        match decoded {
+            Decoded::Char(c) => DecoderEvent::Character(c),
+            Decoded::Text(text) => DecoderEvent::Text(text),
+            Decoded::Prosign(prosign) => DecoderEvent::Prosign(prosign),
+        }
+    }
+}
+
+/// Whether the code is a run of 6 or 7 dits, see [DecoderEvent::ShortError]
+fn is_short_error(code: &MorseCode) -> bool {
+    (6..=7).contains(&code.len())
+        && code
+            .elements()
+            .iter()
+            .all(|&element| element == Element::Dit)
+}
+
+/// The digit that a contest operator's cut number stands for, see
+/// [Decoder::toggle_cut_numbers]
+fn cut_number(c: char) -> Option<char> {
+    match c {
+        't' => Some('0'),
+        'a' => Some('1'),
+        'u' => Some('2'),
+        'v' => Some('3'),
+        'e' => Some('5'),
+        'd' => Some('8'),
+        'n' => Some('9'),
+        _ => None,
+    }
+}
+
+/// Whether cut numbers are being typed as digits
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum CutNumbers {
+    Off,
+    /// Switched on, but the number hasn't started yet, so a word gap doesn't
+    /// switch them off again
+    Waiting,
+    /// In the middle of a number, until the next word gap
+    InNumber,
+}
+
+/// The most events a single call to [Decoder::push] can produce: an element
+/// and the space before it, an error for the sequence that it completes and
+/// its replacement, and a word gap
+const MAX_EVENTS: usize = 5;
+
+/// An iterator over the events produced by a call to [Decoder::push], in the
+/// order they happened
+#[derive(Clone, Debug)]
+pub struct Events {
+    events: [Option<DecoderEvent>; MAX_EVENTS],
+    next: usize,
+}
+
+impl Events {
+    fn new() -> Self {
+        Self {
+            events: [None; MAX_EVENTS],
+            next: 0,
+        }
+    }
+
+    fn add(&mut self, event: DecoderEvent) {
+        if let Some(slot) = self.events.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(event);
+        }
+    }
+}
+
+impl Iterator for Events {
+    type Item = DecoderEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.events.get_mut(self.next)?.take();
+        self.next += 1;
+        event
+    }
+}
+
+/// The timing thresholds used by the decoder. The gaps are measured in dits,
+/// or in Farnsworth spacing units if `effective_wpm` is set.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timing {
+    /// The length of a dit. In adaptive mode this is the starting estimate.
+    pub dit_ms: u64,
+    /// Whether to track the operator's speed rather than using a fixed `dit_ms`
+    pub adaptive: bool,
+    /// Key presses at least this long are dahs rather than dits. Not used in
+    /// adaptive mode, which puts the threshold between the dit and dah lengths.
+    pub dah_threshold: f32,
+    /// Gaps at least this long end the current character
+    pub letter_gap: f32,
+    /// Gaps at least this long end the current word and type a space
+    pub word_gap: f32,
+    /// American Morse only: gaps at least this long, in dits, are the spaces
+    /// inside characters like `C` (`.. .`)
+    pub internal_space: f32,
+    /// American Morse only: replaces `letter_gap`, as the letters are further
+    /// apart to make room for the spaces inside characters
+    pub american_letter_gap: f32,
+    /// American Morse only: dahs at least this long, in dits, are the long
+    /// dash used for `L`
+    pub long_dah: f32,
+    /// American Morse only: dahs at least this long, in dits, are the extra
+    /// long dash used for `0`
+    pub extra_long_dah: f32,
+    /// Key presses shorter than this, in dits, are treated as glitches and
+    /// ignored
+    pub min_element: f32,
+    /// Gaps shorter than this, in dits, are treated as glitches and the key
+    /// presses either side of them are merged into one
+    pub min_gap: f32,
+    /// The overall (Farnsworth) speed in words per minute. Characters are sent
+    /// at the speed given by `dit_ms`, but the gaps between characters and
+    /// words are stretched so the overall speed is slower. `None` uses
+    /// standard spacing.
+    pub effective_wpm: Option<f32>,
+}
+
+impl Timing {
+    /// Standard timing for the given dit length. Letters are separated by a 3
+    /// dit gap and words by a 7 dit gap, so the thresholds sit between these
+    /// and the next shortest gap. Slower operators may want to widen the gaps.
+    ///
+    /// American Morse uses 2 dit spaces inside characters and 4 dit gaps
+    /// between letters, with long dashes of 6 and 9 dits.
+    pub fn new(dit_ms: u64) -> Self {
+        Self {
+            dit_ms,
+            adaptive: false,
+            dah_threshold: 2.0,
+            letter_gap: 2.0,
+            word_gap: 5.0,
+            internal_space: 1.5,
+            american_letter_gap: 3.0,
+            long_dah: 4.5,
+            extra_long_dah: 7.5,
+            min_element: 0.25,
+            min_gap: 0.25,
+            effective_wpm: None,
+        }
+    }
+
+    /// Farnsworth timing for learners, who send characters at
+    /// `character_wpm` but leave longer gaps between them so that their
+    /// overall speed is `effective_wpm`
+    pub fn farnsworth(character_wpm: f32, effective_wpm: f32) -> Self {
+        Self {
+            effective_wpm: Some(effective_wpm),
+            ..Self::new((1200.0 / character_wpm) as u64)
+        }
+    }
+
+    /// Standard timing that adapts to the operator's speed, starting from the
+    /// given dit length
+    pub fn adaptive(dit_ms: u64) -> Self {
+        Self {
+            adaptive: true,
+            ..Self::new(dit_ms)
+        }
+    }
+
+    /// The length of a unit used to measure letter and word gaps, given the
+    /// length of a dit. This is the same as a dit unless Farnsworth timing is
+    /// used, in which case it is stretched using the formula from "A Standard
+    /// for Morse Timing Using the Farnsworth Technique" (ARRL, 1990).
+    pub fn spacing_unit_ms(&self, unit_ms: f32) -> f32 {
+        let character_wpm = 1200.0 / unit_ms;
+
+        match self.effective_wpm {
+            Some(effective_wpm) if effective_wpm < character_wpm => {
+                // the total delay added to a standard word, spread over the 19
+                // spacing units in "PARIS "
+                let delay_s =
+                    (60.0 * character_wpm - 37.2 * effective_wpm) / (character_wpm * effective_wpm);
+                1000.0 * delay_s / 19.0
+            }
+            _ => unit_ms,
+        }
+    }
+}
+
+pub struct Decoder<T: Timestamp> {
+    /// The timing used to split the input into dits, dahs, letters and words
+    pub timing: Timing,
+    /// What to do with sequences that can't be decoded
+    pub error_policy: ErrorPolicy,
+    /// The prosign that toggles cut numbers, if any, see
+    /// [Decoder::toggle_cut_numbers]. The prosign only does that, so it isn't
+    /// reported and doesn't start a word.
+    pub cut_numbers_prosign: Option<Prosign>,
+
+    /// The estimated speed of the operator, used in adaptive mode
+    speed: SpeedTracker,
+    /// The table used to look up completed sequences
+    table: CodeTable,
+    /// The table to go back to when [Prosign::SN] ends a switch to Wabun
+    previous_table: CodeTable,
+    /// The codes in the operator's own table, which take priority over `table`
+    user_codes: UserCodes,
+    /// Whether cut numbers are typed as digits
+    cut_numbers: CutNumbers,
+    /// Holds the dits and dahs for the current character
+    code: MorseCode,
+    /// How long the key was held down for each element in `code`
+    durations_ms: [u64; MAX_CODE_LEN],
+    /// Whether more elements were received than fit in `code`
+    overflowed: bool,
+    /// Whether a character has been sent since the last word gap
+    word_pending: bool,
+    /// Whether the signal is currently high or low, ignoring glitches
+    is_high: bool,
+    /// When the signal last changed, ignoring glitches
+    time_last_changed: T,
+    /// When the signal changed before `time_last_changed`, so the previous gap
+    /// can be picked up again if a key press turns out to be a glitch
+    time_previous_change: T,
+    /// When the last key press started, if it has ended but the gap after it
+    /// isn't long enough yet to be sure it wasn't a glitch
+    pending_press: Option<T>,
+    /// How long the key was up before the pending key press
+    gap_before_ms: u64,
+    /// The number of glitches that have been ignored
+    glitches_rejected: u32,
+}
+
+impl<T: Timestamp> Decoder<T> {
+    /// Creates a decoder, starting at the given time with the key up
+    pub fn new(timing: Timing, now: T) -> Self {
+        Self {
+            timing,
+            error_policy: ErrorPolicy::Ignore,
+            cut_numbers_prosign: None,
+            speed: SpeedTracker::new(timing.dit_ms),
+            table: CodeTable::International,
+            previous_table: CodeTable::International,
+            user_codes: UserCodes::default(),
+            cut_numbers: CutNumbers::Off,
+            code: MorseCode::new(),
+            durations_ms: [0; MAX_CODE_LEN],
+            overflowed: false,
+            word_pending: false,
+            is_high: false,
+            time_last_changed: now,
+            time_previous_change: now,
+            pending_press: None,
+            gap_before_ms: 0,
+            glitches_rejected: 0,
+        }
+    }
+}
+
+/// Private methods
+impl<T: Timestamp> Decoder<T> {
+    /// The length of a single unit, i.e. a dit, in milliseconds
+    fn unit_ms(&self) -> f32 {
+        if self.timing.adaptive {
+            self.speed.unit_ms()
+        } else {
+            self.timing.dit_ms as f32
+        }
+    }
+
+    /// Converts a number of dits into milliseconds
+    fn dits_to_ms(&self, dits: f32) -> u64 {
+        (self.unit_ms() * dits) as u64
+    }
+
+    /// The length of a unit used to measure letter and word gaps
+    fn spacing_unit_ms(&self) -> f32 {
+        self.timing.spacing_unit_ms(self.unit_ms())
+    }
+
+    /// Converts a number of spacing units into milliseconds
+    fn spacing_to_ms(&self, units: f32) -> u64 {
+        (self.spacing_unit_ms() * units) as u64
+    }
+
+    /// The gap that ends the current character, in spacing units
+    fn letter_gap(&self) -> f32 {
+        if self.table.is_american() {
+            self.timing.american_letter_gap
+        } else {
+            self.timing.letter_gap
+        }
+    }
+
+    /// Decides whether a key press of the given length was a dit or a dah, or
+    /// one of the long dashes in American Morse
+    fn classify_element(&mut self, duration_ms: u64) -> Element {
+        if self.table.is_american() {
+            // long dashes are left out of the speed estimate, which only
+            // knows about dits and dahs
+            if duration_ms >= self.dits_to_ms(self.timing.extra_long_dah) {
+                return Element::ExtraLongDah;
+            } else if duration_ms >= self.dits_to_ms(self.timing.long_dah) {
+                return Element::LongDah;
+            }
+        }
+
+        if self.timing.adaptive {
+            self.speed.observe(duration_ms)
+        } else if duration_ms < self.dits_to_ms(self.timing.dah_threshold) {
+            Element::Dit
+        } else {
+            Element::Dah
+        }
+    }
+
+    /// Adds the pending key press to the current sequence, now that the gap
+    /// after it is long enough that it can't be merged with the next press
+    fn commit_pending_press(&mut self, events: &mut Events) {
+        if let Some(started) = self.pending_press.take() {
+            if self.table.is_american()
+                && !self.code.is_empty()
+                && self.gap_before_ms >= self.dits_to_ms(self.timing.internal_space)
+            {
+                self.push_element(Element::Space, self.gap_before_ms, events);
+            }
+
+            let duration_ms = self.time_last_changed.millis_since(started);
+            let element = self.classify_element(duration_ms);
+            self.push_element(element, duration_ms, events);
+        }
+    }
+
+    /// Adds an element to the current sequence and reports it. Elements past
+    /// the end of the sequence are dropped and the sequence is reported as too
+    /// long once the character is complete.
+    fn push_element(&mut self, element: Element, duration_ms: u64, events: &mut Events) {
+        info!("{}", element.as_char());
+        events.add(DecoderEvent::Element {
+            element,
+            duration_ms,
+        });
+
+        let index = self.code.len();
+        if self.code.push(element) {
+            self.durations_ms[index] = duration_ms;
+        } else if !self.overflowed {
+            warn!("Morse sequence is too long, ignoring until the next break");
+            self.overflowed = true;
+        }
+    }
+
+    /// The length of key press that is halfway between a dit and a dah
+    fn dah_threshold_ms(&self) -> f32 {
+        if self.timing.adaptive {
+            self.speed.dah_threshold_ms()
+        } else {
+            self.unit_ms() * self.timing.dah_threshold
+        }
+    }
+
+    /// Switches to or from the Wabun table if the decoded value is
+    /// [Prosign::DO] or [Prosign::SN]
+    fn switch_table(&mut self, decoded: Decoded) {
+        match decoded {
+            Decoded::Prosign(Prosign::DO) if self.table != CodeTable::Wabun => {
+                info!("Switching to Wabun");
+                self.previous_table = self.table;
+                self.table = CodeTable::Wabun;
+            }
+            Decoded::Prosign(Prosign::SN) if self.table == CodeTable::Wabun => {
+                info!("Switching back to {}", self.previous_table);
+                self.table = self.previous_table;
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces a cut number with its digit if cut numbers are on
+    fn replace_cut_number(&mut self, decoded: Decoded) -> Decoded {
+        let Decoded::Char(c) = decoded else {
+            return decoded;
+        };
+        if self.cut_numbers == CutNumbers::Off {
+            return decoded;
+        }
+
+        self.cut_numbers = CutNumbers::InNumber;
+        Decoded::Char(cut_number(c).unwrap_or(c))
+    }
+
+    /// Looks up the current sequence and resets it ready for the next
+    /// character, adding the result to `events`
+    fn finish_character(&mut self, events: &mut Events) {
+        let code = self.code;
+        let overflowed = self.overflowed;
+        let word_pending = self.word_pending;
+        self.code = MorseCode::new();
+        self.overflowed = false;
+        self.word_pending = true;
+
+        let error = if overflowed {
+            DecodeError::TooLong(code)
+        } else if self.user_codes.contains(&code) {
+            info!("Found user code {}", code);
+            events.add(DecoderEvent::UserCode(code));
+            return;
+        } else if let Some(decoded) = self.table.tree().lookup(&code) {
+            info!("Found morse sequence {}", decoded);
+            if let Decoded::Prosign(_) = decoded {
+                // prosigns aren't part of a word, so one on its own isn't
+                // followed by a word gap
+                self.word_pending = word_pending;
+            }
+            if self.cut_numbers_prosign.map(Decoded::Prosign) == Some(decoded) {
+                // nothing is typed for the prosign
+                self.toggle_cut_numbers();
+                return;
+            }
+            self.switch_table(decoded);
+            events.add(self.replace_cut_number(decoded).into());
+            return;
+        } else if is_short_error(&code) {
+            info!("Found short error signal");
+            // like HH, this corrects what was sent rather than starting a word
+            self.word_pending = word_pending;
+            events.add(DecoderEvent::ShortError);
+            return;
+        } else {
+            DecodeError::Unknown(code)
+        };
+
+        events.add(DecoderEvent::Error(error));
+        match self.error_policy {
+            ErrorPolicy::Ignore => {}
+            ErrorPolicy::Placeholder(c) => events.add(DecoderEvent::Character(c)),
+            ErrorPolicy::RawPattern => events.add(DecoderEvent::Pattern(code)),
+            ErrorPolicy::BestGuess => {
+                if let DecodeError::Unknown(code) = error {
+                    let durations_ms = &self.durations_ms[..code.len()];
+                    if let Some(decoded) = guess::best_guess(
+                        self.table.tree(),
+                        &code,
+                        durations_ms,
+                        self.dah_threshold_ms(),
+                    ) {
+                        info!("Guessed morse sequence {}", decoded);
+                        events.add(self.replace_cut_number(decoded).into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Public inteface
+impl<T: Timestamp> Decoder<T> {
+    /// The operator's current sending speed in words per minute. This is fixed
+    /// by `dit_ms` unless the decoder is in adaptive mode.
+    pub fn wpm(&self) -> f32 {
+        1200.0 / self.unit_ms()
+    }
+
+    /// The code table currently used to decode characters
+    pub fn table(&self) -> CodeTable {
+        self.table
+    }
+
+    /// Changes the code table used to decode characters, starting with the
+    /// next one
+    pub fn set_table(&mut self, table: CodeTable) {
+        self.table = table;
+        self.previous_table = table;
+    }
+
+    /// Changes the codes that are reported as a [DecoderEvent::UserCode]
+    /// instead of being looked up in the code table, e.g. after a new user
+    /// table has been loaded
+    pub fn set_user_codes(&mut self, codes: UserCodes) {
+        self.user_codes = codes;
+    }
+
+    /// Switches cut numbers on or off, returning whether they are now on. While
+    /// they are on, the letters that contest operators use as shorthand for
+    /// digits are typed as those digits instead: T for 0, A for 1, U for 2, V
+    /// for 3, E for 5, D for 8 and N for 9, so `5NN` is typed as `599`. They
+    /// switch themselves off at the end of the next word.
+    pub fn toggle_cut_numbers(&mut self) -> bool {
+        self.cut_numbers = match self.cut_numbers {
+            CutNumbers::Off => CutNumbers::Waiting,
+            CutNumbers::Waiting | CutNumbers::InNumber => CutNumbers::Off,
+        };
+        info!("Cut numbers: {}", self.cut_numbers);
+        self.cut_numbers()
+    }
+
+    /// Whether cut numbers are typed as digits, see
+    /// [Decoder::toggle_cut_numbers]
+    pub fn cut_numbers(&self) -> bool {
+        self.cut_numbers != CutNumbers::Off
+    }
+
+    /// The number of key presses and gaps that were too short to be real and
+    /// were ignored, e.g. because of contact bounce
+    pub fn glitches_rejected(&self) -> u32 {
+        self.glitches_rejected
+    }
+
+    /// Throws away the character being sent and carries on from `now` with the
+    /// key up, e.g. after the key couldn't be read for a while so the timing of
+    /// what was sent can't be trusted. The speed, tables and settings are kept,
+    /// and a word that was being sent still ends with a word gap.
+    pub fn reset(&mut self, now: T) {
+        self.code = MorseCode::new();
+        self.overflowed = false;
+        self.is_high = false;
+        self.time_last_changed = now;
+        self.time_previous_change = now;
+        self.pending_press = None;
+        self.gap_before_ms = 0;
+    }
+
+    /// Takes in an input and attempts to parse it into morse code dits and dahs,
+    /// returning anything that was found. This should be called regularly
+    /// (ideally every millisecond) whether or not the input has changed, as
+    /// letters and words are only complete once the key has been up for long
+    /// enough.
+    ///
+    /// Each element is reported once the key has been up for long enough to be
+    /// sure it wasn't a glitch. A character is delineated by a "break" (or a
+    /// low signal) at least as long as the letter gap, and a word by a low
+    /// signal at least as long as the word gap.
+    pub fn push(&mut self, currently_high: bool, change_time: T) -> Events {
+        let mut events = Events::new();
+        let elapsed_ms = change_time.millis_since(self.time_last_changed);
+        let is_high = self.is_high;
+
+        if !is_high && elapsed_ms >= self.dits_to_ms(self.timing.min_gap) {
+            self.commit_pending_press(&mut events);
+        }
+        let press_pending = self.pending_press.is_some();
+
+        if is_high && !currently_high {
+            // falling edge, we've either added a dit or a dah, or a glitch
+            if elapsed_ms < self.dits_to_ms(self.timing.min_element) {
+                // too short to be a real key press, so carry on with the gap
+                // before it as though it never happened
+                debug!("Ignoring a {}ms key press", elapsed_ms);
+                self.glitches_rejected += 1;
+                self.time_last_changed = self.time_previous_change;
+            } else {
+                // wait for a long enough gap before deciding what it was
+                self.pending_press = Some(self.time_last_changed);
+                self.time_last_changed = change_time;
+            }
+            self.is_high = false;
+        } else if !is_high && currently_high {
+            if let Some(started) = self.pending_press.take() {
+                // the gap was too short to be real, so carry on with the
+                // previous key press as though it never ended
+                debug!("Ignoring a {}ms gap", elapsed_ms);
+                self.glitches_rejected += 1;
+                self.time_last_changed = started;
+            } else {
+                self.gap_before_ms = elapsed_ms;
+                self.time_previous_change = self.time_last_changed;
+                self.time_last_changed = change_time;
+            }
+            self.is_high = true;
+        }
+
+        if is_high || press_pending {
+            // the signal was high or has only just gone low, so there is no
+            // gap to measure yet
+            return events;
+        }
+
+        // the signal was low, either continuously or until this rising edge. If
+        // it has been low long enough then the character or word is complete
+        if elapsed_ms >= self.spacing_to_ms(self.letter_gap())
+            && (!self.code.is_empty() || self.overflowed)
+        {
+            info!("BREAK");
+            self.finish_character(&mut events);
+        }
+
+        if elapsed_ms >= self.spacing_to_ms(self.timing.word_gap) && self.word_pending {
+            info!("WORD ({} WPM)", self.wpm());
+            self.word_pending = false;
+            events.add(DecoderEvent::WordGap);
+            if self.cut_numbers == CutNumbers::InNumber {
+                info!("Cut numbers off at the end of the number");
+                self.cut_numbers = CutNumbers::Off;
+            }
+        }
+
+        events
+    }
+}
@@ -0,0 +1,158 @@
+//! Turns text into morse code, as a sequence of key presses and gaps. This is
+//! the opposite of the [decoder](crate::decoder) and uses the same code table,
+//! so anything that is encoded can be decoded again.
+
+use core::str::Chars;
+
+use crate::code_table::{self, CodeTable, CodeTree, Decoded, Element, MorseCode, Prosign};
+use crate::decoder::Timing;
+
+/// The number of spacing units between letters in American Morse
+const AMERICAN_LETTER_GAP: f32 = 4.0;
+
+/// An iterator over the key presses and gaps for some text. Each item is
+/// `(key_down, duration_ms)`, and consecutive items always alternate between
+/// the key being down and up.
+///
+/// Letters and digits are case insensitive, whitespace separates words and
+/// prosigns are written in angle brackets, e.g. `<AR>`. Characters that aren't
+/// in the code table are skipped. If the text ends with whitespace then the
+/// last item is a word gap, otherwise it is the last element.
+pub struct Encoder<'a> {
+    /// The text that is still to be sent
+    text: Chars<'a>,
+    /// The table used to look up the code for each character
+    table: &'static CodeTree,
+    /// The length of a dit
+    dit_ms: u64,
+    /// The length of a unit used for the gaps between characters and words
+    spacing_unit_ms: f32,
+    /// The length of the gap between characters
+    letter_gap_ms: u64,
+    /// The length of the gap between words
+    word_gap_ms: u64,
+    /// The code for the character being sent
+    code: MorseCode,
+    /// The index of the next element of `code` to send
+    element: usize,
+    /// Whether the gap after the last element has been sent
+    gap_sent: bool,
+    /// Whether any characters have been sent yet
+    started: bool,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates an encoder for the given text. Elements use the dit length from
+    /// the timing, and the gaps between characters and words are stretched if
+    /// it uses Farnsworth spacing.
+    pub fn new(text: &'a str, timing: Timing) -> Self {
+        let spacing_unit_ms = timing.spacing_unit_ms(timing.dit_ms as f32);
+
+        Self {
+            text: text.chars(),
+            table: &code_table::ITU,
+            dit_ms: timing.dit_ms,
+            spacing_unit_ms,
+            letter_gap_ms: (3.0 * spacing_unit_ms) as u64,
+            word_gap_ms: (7.0 * spacing_unit_ms) as u64,
+            code: MorseCode::new(),
+            element: 0,
+            gap_sent: false,
+            started: false,
+        }
+    }
+
+    /// Uses a different code table, instead of the international one.
+    /// American Morse leaves longer gaps between letters so they can't be
+    /// mistaken for the spaces inside characters.
+    pub fn with_table(mut self, table: CodeTable) -> Self {
+        self.table = table.tree();
+        if table.is_american() {
+            self.letter_gap_ms = (AMERICAN_LETTER_GAP * self.spacing_unit_ms) as u64;
+        }
+        self
+    }
+}
+
+/// Private methods
+impl Encoder<'_> {
+    /// Finds the code for a character, or for the prosign it starts
+    fn code_for(&mut self, c: char) -> Option<MorseCode> {
+        if c == '<' {
+            let rest = self.text.as_str();
+            if let Some(end) = rest.find('>')
+                && let Some(prosign) = Prosign::from_name(&rest[..end])
+            {
+                self.text = rest[end + 1..].chars();
+                return self.table.encode(Decoded::Prosign(prosign));
+            }
+        }
+
+        let c = c.to_lowercase().next().unwrap_or(c);
+        self.table.encode(Decoded::Char(c)).or_else(|| {
+            // some characters share their code with a prosign
+            Prosign::ALL
+                .into_iter()
+                .find(|prosign| prosign.as_char() == Some(c))
+                .and_then(|prosign| self.table.encode(Decoded::Prosign(prosign)))
+        })
+    }
+}
+
+impl Iterator for Encoder<'_> {
+    type Item = (bool, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(&element) = self.code.elements().get(self.element) {
+            if element == Element::Space {
+                // a space replaces the usual gap before the next element
+                self.element += 1;
+                self.gap_sent = true;
+                return Some((false, element.units() * self.dit_ms));
+            }
+
+            if self.element > 0 && !self.gap_sent {
+                self.gap_sent = true;
+                return Some((false, self.dit_ms));
+            }
+
+            self.gap_sent = false;
+            self.element += 1;
+            return Some((true, element.units() * self.dit_ms));
+        }
+
+        // the current character is finished, so find the next one
+        let mut word_gap = false;
+        let code = loop {
+            let Some(c) = self.text.next() else {
+                return (word_gap && self.started).then_some((false, self.word_gap_ms));
+            };
+
+            if c.is_whitespace() {
+                word_gap = true;
+            } else if let Some(code) = self.code_for(c) {
+                break code;
+            } else {
+                warn!("Unable to encode {}", c);
+            }
+        };
+
+        self.code = code;
+        self.element = 0;
+        self.gap_sent = false;
+
+        if !self.started {
+            self.started = true;
+            return self.next();
+        }
+
+        Some((
+            false,
+            if word_gap {
+                self.word_gap_ms
+            } else {
+                self.letter_gap_ms
+            },
+        ))
+    }
+}
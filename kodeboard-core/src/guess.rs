@@ -0,0 +1,92 @@
+//! Guesses what an undecodable sequence was meant to be. Every code in the
+//! table is compared with the sequence using an edit distance, where swapping
+//! a dit for a dah is cheap if the key press was close to the dah threshold
+//! and adding or removing an element always costs one. Any other swap, e.g. of
+//! an American Morse long dash, costs the same as removing one element and
+//! adding another.
+
+use crate::code_table::{CodeTree, Decoded, Element, MAX_CODE_LEN, MorseCode};
+
+/// The cost of adding or removing an element
+const INSERT_DELETE_COST: f32 = 1.0;
+
+/// The cost of reading an element that was sent as `sent`, lasting
+/// `duration_ms`, as `element` instead. A press right on the threshold could
+/// easily have been either a dit or a dah, while a press of the standard length
+/// for a dit or a dah costs as much as adding or removing an element.
+fn substitution_cost(
+    sent: Element,
+    duration_ms: u64,
+    dah_threshold_ms: f32,
+    element: Element,
+) -> f32 {
+    let is_dit_or_dah = |element| matches!(element, Element::Dit | Element::Dah);
+
+    if sent == element {
+        0.0
+    } else if !is_dit_or_dah(sent) || !is_dit_or_dah(element) {
+        2.0 * INSERT_DELETE_COST
+    } else {
+        // a standard dit is half the threshold and a standard dah is one and
+        // a half times the threshold
+        let ratio = duration_ms as f32 / dah_threshold_ms;
+        2.0 * INSERT_DELETE_COST * (ratio - 1.0).abs()
+    }
+}
+
+/// The weighted edit distance between the elements that were sent and a code
+/// from the table
+fn distance(
+    sent: &MorseCode,
+    durations_ms: &[u64],
+    dah_threshold_ms: f32,
+    code: &MorseCode,
+) -> f32 {
+    let code = code.elements();
+
+    // the classic dynamic programming approach, keeping one row at a time
+    let mut previous = [0.0; MAX_CODE_LEN + 1];
+    let mut current = [0.0; MAX_CODE_LEN + 1];
+    for (j, cost) in previous.iter_mut().enumerate().take(code.len() + 1) {
+        *cost = j as f32 * INSERT_DELETE_COST;
+    }
+
+    for (i, (&sent, &duration_ms)) in sent.elements().iter().zip(durations_ms).enumerate() {
+        current[0] = (i + 1) as f32 * INSERT_DELETE_COST;
+        for (j, &element) in code.iter().enumerate() {
+            let substitute =
+                previous[j] + substitution_cost(sent, duration_ms, dah_threshold_ms, element);
+            let delete = previous[j + 1] + INSERT_DELETE_COST;
+            let insert = current[j] + INSERT_DELETE_COST;
+            current[j + 1] = substitute.min(delete).min(insert);
+        }
+        previous = current;
+    }
+
+    previous[code.len()]
+}
+
+/// Finds the code in the table that was most likely meant by the elements that
+/// were sent, where `durations_ms` has the length of each one and presses at
+/// least `dah_threshold_ms` long were read as dahs. Prosigns are never guessed,
+/// as they press keys rather than type, so this returns a character or text, or
+/// `None` if the table has neither.
+pub fn best_guess(
+    table: &CodeTree,
+    sent: &MorseCode,
+    durations_ms: &[u64],
+    dah_threshold_ms: f32,
+) -> Option<Decoded> {
+    let mut best: Option<(f32, Decoded)> = None;
+    table.for_each_entry(|code, value| {
+        if let Decoded::Prosign(_) = value {
+            return;
+        }
+        let cost = distance(sent, durations_ms, dah_threshold_ms, code);
+        if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+            best = Some((cost, value));
+        }
+    });
+
+    best.map(|(_, value)| value)
+}
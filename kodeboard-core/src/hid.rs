@@ -0,0 +1,381 @@
+//! The state behind the keyboard's HID interface: which protocol the host has
+//! asked for, how often it wants reports repeated, and the keys that are down.
+//!
+//! Hosts that don't parse report descriptors, like BIOS setup screens and
+//! bootloaders, switch the keyboard to the boot protocol. The input report is
+//! laid out like the boot keyboard report either way, so the same report can
+//! be sent whichever protocol is in use. Hosts that support it can be sent an
+//! NKRO bitmap report instead, using [NKRO_REPORT_DESCRIPTOR], which falls
+//! back to the boot report under the boot protocol.
+//!
+//! Key strokes are turned into reports by a [ReportQueue], which holds keys
+//! down for as long as it can so that text is typed quickly.
+
+use crate::layout::{KeySequence, KeyStroke, MAX_STROKES};
+use crate::time::Timestamp;
+
+/// The length of a keyboard input report
+pub const REPORT_LEN: usize = 8;
+
+/// The length of an NKRO input report: the modifiers, then one bit for each
+/// key code below 128
+pub const NKRO_REPORT_LEN: usize = 17;
+
+/// The length of the longest report
+pub const MAX_REPORT_LEN: usize = NKRO_REPORT_LEN;
+
+/// The report descriptor for NKRO reports, with the same LED output report
+/// as the boot keyboard
+pub const NKRO_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    // the modifiers, one bit each
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0xE0, //   Usage Minimum (Left Control)
+    0x29, 0xE7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    // the keys, one bit each
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0x7F, //   Usage Maximum (127)
+    0x95, 0x80, //   Report Count (128)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    // the LEDs
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (Num Lock)
+    0x29, 0x05, //   Usage Maximum (Kana)
+    0x95, 0x05, //   Report Count (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0x75, 0x03, //   Report Size (3)
+    0x95, 0x01, //   Report Count (1)
+    0x91, 0x01, //   Output (Constant)
+    0xC0, // End Collection
+];
+
+/// The most keys that can be held down in one report
+pub const MAX_KEYS: usize = 6;
+
+/// The report formats the host can ask for with SET_PROTOCOL
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Protocol {
+    /// The fixed boot keyboard report
+    Boot,
+    /// The report described by the report descriptor
+    #[default]
+    Report,
+}
+
+/// A keyboard input report, with the modifiers and keys that are held down
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyReport {
+    pub modifier: u8,
+    pub keycodes: [u8; MAX_KEYS],
+}
+
+impl KeyReport {
+    /// A report with the given modifiers and a single key, or no key at all if
+    /// the key code is zero
+    pub const fn new(modifier: u8, keycode: u8) -> Self {
+        Self {
+            modifier,
+            keycodes: [keycode, 0, 0, 0, 0, 0],
+        }
+    }
+
+    /// The report as it is sent to the host, in the boot keyboard layout
+    pub fn to_bytes(&self) -> [u8; REPORT_LEN] {
+        let mut bytes = [0; REPORT_LEN];
+        bytes[0] = self.modifier;
+        bytes[2..].copy_from_slice(&self.keycodes);
+        bytes
+    }
+
+    /// The report as an NKRO bitmap. Key codes of 128 and above can't be sent
+    /// this way, but none of the layouts use them.
+    pub fn to_nkro_bytes(&self) -> [u8; NKRO_REPORT_LEN] {
+        let mut bytes = [0; NKRO_REPORT_LEN];
+        bytes[0] = self.modifier;
+        for &keycode in &self.keycodes {
+            if keycode != 0 && keycode < 128 {
+                bytes[1 + usize::from(keycode / 8)] |= 1 << (keycode % 8);
+            }
+        }
+        bytes
+    }
+
+    /// The number of keys held down
+    pub fn key_count(&self) -> usize {
+        self.keycodes
+            .iter()
+            .filter(|&&keycode| keycode != 0)
+            .count()
+    }
+
+    /// Whether the given key is held down
+    pub fn contains(&self, keycode: u8) -> bool {
+        keycode != 0 && self.keycodes.contains(&keycode)
+    }
+}
+
+/// A report encoded for the protocol the host is using
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncodedReport {
+    bytes: [u8; MAX_REPORT_LEN],
+    len: usize,
+}
+
+impl EncodedReport {
+    fn new(report: &[u8]) -> Self {
+        let mut bytes = [0; MAX_REPORT_LEN];
+        bytes[..report.len()].copy_from_slice(report);
+        Self {
+            bytes,
+            len: report.len(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Returned when there isn't room in a [ReportQueue]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueFull;
+
+/// The most reports that can be waiting, which is enough for the longest
+/// [KeySequence]: a release and a press for each stroke, and a release at the
+/// end
+const QUEUE_LEN: usize = 2 * MAX_STROKES + 1;
+
+/// Turns key strokes into the reports that type them, in order.
+///
+/// Each report presses one more key than the last, so the host still sees the
+/// keys in the right order, and keys are only released when they have to be:
+/// when a key is pressed again, when the modifiers change, or when all six
+/// keys are already down. Whatever is left down is released by
+/// [ReportQueue::release_all], which should be done before waiting for
+/// anything so the host doesn't start repeating keys.
+#[derive(Clone, Debug)]
+pub struct ReportQueue {
+    reports: [KeyReport; QUEUE_LEN],
+    start: usize,
+    len: usize,
+    /// The last report queued, which the next key is added to
+    held: KeyReport,
+}
+
+impl Default for ReportQueue {
+    fn default() -> Self {
+        Self {
+            reports: [KeyReport::default(); QUEUE_LEN],
+            start: 0,
+            len: 0,
+            held: KeyReport::default(),
+        }
+    }
+}
+
+impl ReportQueue {
+    /// The number of reports waiting to be sent
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The keys that will be down once every waiting report has been sent
+    pub fn held(&self) -> KeyReport {
+        self.held
+    }
+
+    /// Takes the next report to send
+    pub fn pop(&mut self) -> Option<KeyReport> {
+        if self.len == 0 {
+            return None;
+        }
+        let report = self.reports[self.start];
+        self.start = (self.start + 1) % QUEUE_LEN;
+        self.len -= 1;
+        Some(report)
+    }
+
+    /// Queues the reports for a single key stroke
+    pub fn press(&mut self, stroke: KeyStroke) -> Result<(), QueueFull> {
+        self.push_keys(&stroke.into())
+    }
+
+    /// Queues the reports that type a sequence of key strokes. Modifiers the
+    /// sequence holds down are released at the end of it, as they usually
+    /// finish entering a character.
+    pub fn push_keys(&mut self, keys: &KeySequence) -> Result<(), QueueFull> {
+        let strokes = keys.strokes();
+        if QUEUE_LEN - self.len < 2 * strokes.len() + 1 {
+            return Err(QueueFull);
+        }
+        for stroke in strokes {
+            self.add_key(stroke.modifier | keys.held(), stroke.keycode);
+        }
+        if keys.held() != 0 {
+            self.push(KeyReport::default());
+        }
+        Ok(())
+    }
+
+    /// Queues a report that releases every key and modifier, if any are down
+    pub fn release_all(&mut self) -> Result<(), QueueFull> {
+        if self.held != KeyReport::default() {
+            if self.len == QUEUE_LEN {
+                return Err(QueueFull);
+            }
+            self.push(KeyReport::default());
+        }
+        Ok(())
+    }
+
+    /// Adds a key to the held keys, releasing them first if it can't be
+    fn add_key(&mut self, modifier: u8, keycode: u8) {
+        let count = self.held.key_count();
+        if count > 0
+            && (modifier != self.held.modifier || self.held.contains(keycode) || count == MAX_KEYS)
+        {
+            // release the keys but not the modifiers, which change with the
+            // next key instead
+            self.push(KeyReport::new(self.held.modifier, 0));
+        }
+
+        let mut report = self.held;
+        report.modifier = modifier;
+        if let Some(slot) = report.keycodes.iter_mut().find(|slot| **slot == 0) {
+            *slot = keycode;
+        }
+        self.push(report);
+    }
+
+    /// Adds a report to the end of the queue, which the caller has checked
+    /// there is room for
+    fn push(&mut self, report: KeyReport) {
+        self.reports[(self.start + self.len) % QUEUE_LEN] = report;
+        self.len += 1;
+        self.held = report;
+    }
+}
+
+/// Keeps track of the HID class requests from the host and the last report
+/// that was sent, so it can be read back with GET_REPORT and repeated at the
+/// idle rate
+#[derive(Clone, Copy, Debug)]
+pub struct HidState<T: Timestamp> {
+    protocol: Protocol,
+    /// Whether the report descriptor is [NKRO_REPORT_DESCRIPTOR]
+    nkro: bool,
+    /// The idle rate, in the 4 ms units of SET_IDLE
+    idle: u8,
+    report: KeyReport,
+    sent_at: Option<T>,
+}
+
+impl<T: Timestamp> HidState<T> {
+    /// The idle rate a keyboard starts with, as recommended by the HID spec
+    pub const DEFAULT_IDLE_MS: u32 = 500;
+
+    /// The length of one unit of the idle rate in SET_IDLE and GET_IDLE
+    pub const IDLE_UNIT_MS: u32 = 4;
+
+    /// Creates the state for a keyboard that sends NKRO reports under the
+    /// report protocol, or boot keyboard reports otherwise
+    pub const fn new(nkro: bool) -> Self {
+        Self {
+            protocol: Protocol::Report,
+            nkro,
+            idle: (Self::DEFAULT_IDLE_MS / Self::IDLE_UNIT_MS) as u8,
+            report: KeyReport::new(0, 0),
+            sent_at: None,
+        }
+    }
+
+    /// Goes back to how the keyboard starts, e.g. after a bus reset. The keys
+    /// that are down are kept, as they haven't been released.
+    pub fn reset(&mut self) {
+        self.protocol = Protocol::Report;
+        self.idle = (Self::DEFAULT_IDLE_MS / Self::IDLE_UNIT_MS) as u8;
+        self.sent_at = None;
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// How often the last report is repeated while nothing changes, where
+    /// zero means it is only sent when it changes
+    pub fn idle_ms(&self) -> u32 {
+        u32::from(self.idle) * Self::IDLE_UNIT_MS
+    }
+
+    /// The idle rate for GET_IDLE, in units of [Self::IDLE_UNIT_MS]
+    pub fn idle(&self) -> u8 {
+        self.idle
+    }
+
+    /// Sets the idle rate from the upper byte of a SET_IDLE request, in units
+    /// of [Self::IDLE_UNIT_MS], where zero means the report is only sent when
+    /// it changes
+    pub fn set_idle(&mut self, idle: u8) {
+        self.idle = idle;
+    }
+
+    /// The keys that are down, for GET_REPORT
+    pub fn report(&self) -> KeyReport {
+        self.report
+    }
+
+    /// Encodes a report for the protocol the host is using
+    pub fn encode(&self, report: KeyReport) -> EncodedReport {
+        if self.nkro && self.protocol == Protocol::Report {
+            EncodedReport::new(&report.to_nkro_bytes())
+        } else {
+            EncodedReport::new(&report.to_bytes())
+        }
+    }
+
+    /// Records that a report was sent to the host
+    pub fn sent(&mut self, report: KeyReport, now: T) {
+        self.report = report;
+        self.sent_at = Some(now);
+    }
+
+    /// When the last report should be sent again if nothing changes before
+    /// then, or `None` if it shouldn't be. Nothing is repeated until the first
+    /// report has been sent.
+    pub fn next_repeat(&self) -> Option<T> {
+        let sent_at = self.sent_at?;
+        if self.idle == 0 {
+            return None;
+        }
+        Some(sent_at.add_millis(u64::from(self.idle_ms())))
+    }
+
+    /// The report to send again if the idle rate has passed since the last one
+    /// was sent
+    pub fn repeat(&mut self, now: T) -> Option<KeyReport> {
+        if self.next_repeat()? > now {
+            return None;
+        }
+        self.sent_at = Some(now);
+        Some(self.report)
+    }
+}
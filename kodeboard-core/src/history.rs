@@ -0,0 +1,89 @@
+//! Keeps track of what has been typed on the host, so that mistakes can be
+//! corrected by sending the right number of backspaces. Only the most recent
+//! characters are remembered, which is plenty for deleting the last word.
+
+use crate::key_mapping::ControlKey;
+
+/// The number of characters that are remembered
+const CAPACITY: usize = 64;
+
+/// The characters most recently typed on the host, oldest first. Enter is
+/// recorded as `\n` and Tab as `\t`.
+#[derive(Clone, Debug)]
+pub struct TypedHistory {
+    typed: [char; CAPACITY],
+    len: usize,
+}
+
+impl Default for TypedHistory {
+    fn default() -> Self {
+        Self {
+            typed: ['\0'; CAPACITY],
+            len: 0,
+        }
+    }
+}
+
+impl TypedHistory {
+    /// The remembered characters, oldest first
+    pub fn typed(&self) -> &[char] {
+        &self.typed[..self.len]
+    }
+
+    /// Records that a character was typed on the host
+    pub fn push(&mut self, c: char) {
+        if self.len == CAPACITY {
+            // forget the oldest character
+            self.typed.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.typed[self.len] = c;
+        self.len += 1;
+    }
+
+    /// Records that a control key was pressed on the host
+    pub fn press(&mut self, key: ControlKey) {
+        match key {
+            ControlKey::Enter => self.push('\n'),
+            ControlKey::Tab => self.push('\t'),
+            ControlKey::Backspace => {
+                self.len = self.len.saturating_sub(1);
+            }
+            // the host may have moved somewhere else entirely
+            ControlKey::Escape => self.clear(),
+            ControlKey::CapsLock => {}
+        }
+    }
+
+    /// Forgets everything that has been typed, e.g. if the host may have lost
+    /// or changed it
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Removes the last character, returning the number of backspaces to send.
+    /// This is always one, even if nothing has been typed that is remembered,
+    /// as the operator wants to delete whatever is there.
+    pub fn delete_character(&mut self) -> usize {
+        self.len = self.len.saturating_sub(1);
+        1
+    }
+
+    /// Removes the last word along with any whitespace after it, returning the
+    /// number of backspaces to send. This is zero if nothing has been typed
+    /// that is remembered, so text that wasn't typed by the keyboard is never
+    /// deleted.
+    pub fn delete_word(&mut self) -> usize {
+        let typed = self.typed();
+        let spaces = typed.iter().rev().take_while(|c| c.is_whitespace()).count();
+        let letters = typed[..typed.len() - spaces]
+            .iter()
+            .rev()
+            .take_while(|c| !c.is_whitespace())
+            .count();
+
+        let count = spaces + letters;
+        self.len -= count;
+        count
+    }
+}
@@ -0,0 +1,96 @@
+//! The settings for the computer the keyboard is plugged into, which decide how
+//! each character is typed.
+
+use crate::layout::{KeySequence, KeyStroke, Layout, SHIFT};
+use crate::unicode::UnicodeEntry;
+
+/// The keyboard LEDs the host has turned on, which it sends in an output
+/// report whenever they change
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HostLeds(u8);
+
+impl HostLeds {
+    /// The bit in the output report for Num Lock
+    pub const NUM_LOCK: u8 = 0x01;
+    /// The bit in the output report for Caps Lock
+    pub const CAPS_LOCK: u8 = 0x02;
+    /// The bit in the output report for Scroll Lock
+    pub const SCROLL_LOCK: u8 = 0x04;
+
+    /// Reads the LEDs from a keyboard output report, or `None` if it is empty
+    pub fn from_report(report: &[u8]) -> Option<Self> {
+        report.first().map(|&bits| Self(bits))
+    }
+
+    pub fn num_lock(self) -> bool {
+        self.0 & Self::NUM_LOCK != 0
+    }
+
+    pub fn caps_lock(self) -> bool {
+        self.0 & Self::CAPS_LOCK != 0
+    }
+
+    pub fn scroll_lock(self) -> bool {
+        self.0 & Self::SCROLL_LOCK != 0
+    }
+
+    /// Flips Caps Lock, e.g. after pressing it and before the host has sent
+    /// its new LEDs
+    pub fn toggle_caps_lock(&mut self) {
+        self.0 ^= Self::CAPS_LOCK;
+    }
+}
+
+/// How to type characters on a particular host
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Host {
+    /// The keyboard layout the host is set to
+    pub layout: Layout,
+    /// How to enter characters that aren't on the layout, or `None` if they
+    /// can't be typed
+    pub unicode_entry: Option<UnicodeEntry>,
+}
+
+impl Host {
+    /// The key strokes that type the given character, using the layout if it
+    /// has the character and entering its code point otherwise
+    pub fn keys_for(&self, c: char) -> Option<KeySequence> {
+        self.layout
+            .keys_for(c)
+            .or_else(|| self.unicode_entry?.keys_for(c, self.layout))
+    }
+
+    /// The character typed by adding shift to the key for the given one, e.g.
+    /// `!` for `1` on a US layout. Letters are put in upper case, so they come
+    /// out right on any layout, and characters that shift doesn't change or
+    /// that aren't typed with a single key are left as they are.
+    pub fn shifted(&self, c: char) -> char {
+        // `ß` has no single upper case letter, so it is typed with its key
+        let mut upper = c.to_uppercase();
+        if let (Some(upper), None) = (upper.next(), upper.next())
+            && upper != c
+        {
+            return upper;
+        }
+        match self.layout.key_for(c) {
+            Some(stroke) if stroke.modifier & SHIFT == 0 => self
+                .layout
+                .char_for(KeyStroke::new(stroke.modifier | SHIFT, stroke.keycode))
+                .unwrap_or(c),
+            _ => c,
+        }
+    }
+
+    /// The key strokes that type the given character while the host's Caps
+    /// Lock is on or off. Caps Lock swaps the case of letters, so shift is
+    /// swapped on the keys for them to come out as they should.
+    pub fn keys_for_caps_lock(&self, c: char, caps_lock: bool) -> Option<KeySequence> {
+        let mut keys = self.keys_for(c)?;
+        if caps_lock {
+            keys.swap_letter_case(self.layout);
+        }
+        Some(keys)
+    }
+}
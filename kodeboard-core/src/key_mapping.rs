@@ -0,0 +1,136 @@
+use crate::code_table::{Decoded, Prosign};
+use crate::modifiers::Modifier;
+
+/// Non-printing keys that can be sent by a prosign
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControlKey {
+    Enter,
+    Tab,
+    Escape,
+    Backspace,
+    CapsLock,
+}
+
+impl ControlKey {
+    /// The HID key code for this key
+    pub fn hid_code(self) -> u8 {
+        match self {
+            ControlKey::Enter => 0x28,
+            ControlKey::Escape => 0x29,
+            ControlKey::Backspace => 0x2A,
+            ControlKey::Tab => 0x2B,
+            ControlKey::CapsLock => 0x39,
+        }
+    }
+}
+
+/// What a prosign does when it is received
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProsignAction {
+    /// Presses a control key
+    Key(ControlKey),
+    /// Deletes the last character that was typed
+    DeleteCharacter,
+    /// Deletes the last word that was typed, along with any spaces after it
+    DeleteWord,
+    /// Picks a modifier for the characters that follow, see
+    /// [Modifiers::pick](crate::modifiers::Modifiers::pick)
+    Modifier(Modifier),
+    /// Keeps the modifiers picked for the next character on until the end of
+    /// the word, see
+    /// [Modifiers::extend_to_word](crate::modifiers::Modifiers::extend_to_word)
+    WordModifiers,
+}
+
+/// Maps each [Prosign] to the action that it performs. A prosign without an
+/// action is typed as its equivalent character instead (if it has one), and
+/// one with an action can still be typed as a character while holding shift,
+/// see [shifted_prosign_action].
+#[derive(Clone, Copy, Debug)]
+pub struct ProsignKeyMap {
+    actions: [Option<ProsignAction>; Prosign::COUNT],
+}
+
+impl Default for ProsignKeyMap {
+    fn default() -> Self {
+        let mut map = Self {
+            actions: [None; Prosign::COUNT],
+        };
+        map.set(Prosign::AR, Some(ProsignAction::Key(ControlKey::Enter)));
+        map.set(Prosign::BT, Some(ProsignAction::Key(ControlKey::Tab)));
+        map.set(Prosign::KN, Some(ProsignAction::Key(ControlKey::Backspace)));
+        map.set(Prosign::SK, Some(ProsignAction::Key(ControlKey::Escape)));
+        map.set(Prosign::HH, Some(ProsignAction::DeleteWord));
+        map.set(Prosign::CT, Some(ProsignAction::Modifier(Modifier::Ctrl)));
+        map
+    }
+}
+
+impl ProsignKeyMap {
+    /// Gets the action performed by the given prosign
+    pub fn get(&self, prosign: Prosign) -> Option<ProsignAction> {
+        self.actions[prosign as usize]
+    }
+
+    /// Changes the action performed by the given prosign. Passing `None` means
+    /// the prosign is typed as its equivalent character.
+    pub fn set(&mut self, prosign: Prosign, action: Option<ProsignAction>) {
+        self.actions[prosign as usize] = action;
+    }
+}
+
+/// Something to do on the host in response to a decoded morse sequence
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyAction {
+    /// Types a character
+    Type(char),
+    /// Types each character in a string
+    TypeText(&'static str),
+    /// Presses a control key
+    Control(ControlKey),
+    /// Deletes the last character that was typed
+    DeleteCharacter,
+    /// Deletes the last word that was typed
+    DeleteWord,
+    /// Picks a modifier for the characters that follow
+    Modifier(Modifier),
+    /// Keeps the modifiers picked for the next character on until the end of
+    /// the word
+    WordModifiers,
+}
+
+/// Decides what to do for a decoded morse sequence, or `None` if it does
+/// nothing
+pub fn decoded_to_action(decoded: Decoded, prosign_keys: &ProsignKeyMap) -> Option<KeyAction> {
+    match decoded {
+        Decoded::Char(c) => Some(KeyAction::Type(c)),
+        Decoded::Text(text) => Some(KeyAction::TypeText(text)),
+        Decoded::Prosign(prosign) => match prosign_keys.get(prosign) {
+            Some(ProsignAction::Key(key)) => Some(KeyAction::Control(key)),
+            Some(ProsignAction::DeleteCharacter) => Some(KeyAction::DeleteCharacter),
+            Some(ProsignAction::DeleteWord) => Some(KeyAction::DeleteWord),
+            Some(ProsignAction::Modifier(modifier)) => Some(KeyAction::Modifier(modifier)),
+            Some(ProsignAction::WordModifiers) => Some(KeyAction::WordModifiers),
+            // the decoder switches tables on these, so there is nothing to type
+            None if matches!(prosign, Prosign::SN | Prosign::DO) => None,
+            None => {
+                let c = prosign.as_char();
+                if c.is_none() {
+                    warn!("unmapped prosign: {}", prosign);
+                }
+                c.map(KeyAction::Type)
+            }
+        },
+    }
+}
+
+/// Decides what to do for a prosign sent while the shift button is held, which
+/// types the character it shares its code with whatever it is mapped to, so `+`
+/// can still be typed while AR presses Enter. Returns `None` for prosigns
+/// without a character, which do their usual action.
+pub fn shifted_prosign_action(prosign: Prosign) -> Option<KeyAction> {
+    prosign.as_char().map(KeyAction::Type)
+}
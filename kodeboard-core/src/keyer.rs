@@ -0,0 +1,241 @@
+//! An electronic keyer for a dual-lever paddle. One paddle sends dits and the
+//! other sends dahs, and the keyer generates perfectly timed elements from
+//! them. The keyer is a state machine driven purely by the timestamps that are
+//! passed to [Keyer::update], so it doesn't need to know about the hardware.
+
+use crate::Timestamp;
+use crate::code_table::Element;
+
+/// How the keyer behaves when both paddles are squeezed together
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyerMode {
+    /// Squeezing sends alternating dits and dahs. Releasing both paddles stops
+    /// after the current element.
+    IambicA,
+    /// Like [KeyerMode::IambicA], but if the paddles were squeezed at any point
+    /// during the current element then one extra opposite element is sent after
+    /// they are released.
+    IambicB,
+    /// Squeezing repeats the element for whichever paddle was pressed last
+    Ultimatic,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyerConfig {
+    /// The length of a dit
+    pub dit_ms: u64,
+    /// What to do when both paddles are squeezed
+    pub mode: KeyerMode,
+    /// Whether pressing the opposite paddle during an element is remembered,
+    /// so that its element is sent next even if the paddle has been released
+    pub memory: bool,
+    /// Swaps the dit and dah paddles, e.g. for left-handed operators
+    pub reversed: bool,
+}
+
+impl KeyerConfig {
+    /// An Iambic B keyer with dot and dash memory at the given speed
+    pub fn new(dit_ms: u64) -> Self {
+        Self {
+            dit_ms,
+            mode: KeyerMode::IambicB,
+            memory: true,
+            reversed: false,
+        }
+    }
+}
+
+/// A change in the keyer's output, along with the exact time it happened. This
+/// may be a little earlier than the time passed to [Keyer::update].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyEdge<T> {
+    pub key_down: bool,
+    pub at: T,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum State<T> {
+    Idle,
+    Sending {
+        /// The element being sent
+        element: Element,
+        /// Whether the key is still down for this element
+        key_down: bool,
+        /// When the key is released at the end of the element
+        key_up_at: T,
+        /// When the gap after the element ends and the next element can start
+        ends_at: T,
+    },
+}
+
+pub struct Keyer<T: Timestamp> {
+    pub config: KeyerConfig,
+
+    state: State<T>,
+    /// Whether a dit has been remembered to send after the current element
+    dit_memory: bool,
+    /// Whether a dah has been remembered to send after the current element
+    dah_memory: bool,
+    /// The element that was sent last, used to alternate when squeezing
+    last_element: Option<Element>,
+    /// The paddle that was pressed most recently, used in Ultimatic mode
+    last_pressed: Option<Element>,
+    /// The state of the dit paddle the last time the keyer was updated
+    prev_dit: bool,
+    /// The state of the dah paddle the last time the keyer was updated
+    prev_dah: bool,
+}
+
+impl<T: Timestamp> Keyer<T> {
+    pub fn new(config: KeyerConfig) -> Self {
+        Self {
+            config,
+            state: State::Idle,
+            dit_memory: false,
+            dah_memory: false,
+            last_element: None,
+            last_pressed: None,
+            prev_dit: false,
+            prev_dah: false,
+        }
+    }
+}
+
+/// Private methods
+impl<T: Timestamp> Keyer<T> {
+    /// Remembers the opposite paddle if it was pressed while sending `element`
+    fn latch_memory(&mut self, element: Element, dit: bool, dah: bool) {
+        if !self.config.memory {
+            return;
+        }
+
+        // in Iambic B holding the opposite paddle at any point is enough,
+        // otherwise it must have been pressed during this element
+        let (dit, dah) = match self.config.mode {
+            KeyerMode::IambicB => (dit, dah),
+            KeyerMode::IambicA | KeyerMode::Ultimatic => {
+                (dit && !self.prev_dit, dah && !self.prev_dah)
+            }
+        };
+
+        // the keyer only ever sends dits and dahs
+        if element == Element::Dit {
+            self.dah_memory |= dah;
+        } else {
+            self.dit_memory |= dit;
+        }
+    }
+
+    /// Decides which element to send next based on the paddles and memory
+    fn next_element(&mut self, dit: bool, dah: bool) -> Option<Element> {
+        let dit_memory = self.dit_memory;
+        let dah_memory = self.dah_memory;
+        self.dit_memory = false;
+        self.dah_memory = false;
+
+        if dit && dah {
+            return Some(match self.config.mode {
+                KeyerMode::IambicA | KeyerMode::IambicB => match self.last_element {
+                    Some(Element::Dit) => Element::Dah,
+                    _ => Element::Dit,
+                },
+                KeyerMode::Ultimatic => self.last_pressed.unwrap_or(Element::Dit),
+            });
+        }
+
+        if dah_memory {
+            Some(Element::Dah)
+        } else if dit_memory || dit {
+            Some(Element::Dit)
+        } else if dah {
+            Some(Element::Dah)
+        } else {
+            None
+        }
+    }
+
+    /// Starts sending an element at the given time
+    fn start_element(&mut self, element: Element, at: T) -> KeyEdge<T> {
+        let key_up_at = at.add_millis(element.units() * self.config.dit_ms);
+
+        self.last_element = Some(element);
+        self.state = State::Sending {
+            element,
+            key_down: true,
+            key_up_at,
+            ends_at: key_up_at.add_millis(self.config.dit_ms),
+        };
+
+        KeyEdge { key_down: true, at }
+    }
+}
+
+/// Public interface
+impl<T: Timestamp> Keyer<T> {
+    /// Whether the keyer currently has the key down
+    pub fn is_key_down(&self) -> bool {
+        matches!(self.state, State::Sending { key_down: true, .. })
+    }
+
+    /// Updates the keyer with the current state of the paddles. Returns a
+    /// [KeyEdge] if the key went up or down. Only one edge is returned per
+    /// update, so this should be called at least once per millisecond.
+    pub fn update(&mut self, now: T, dit_paddle: bool, dah_paddle: bool) -> Option<KeyEdge<T>> {
+        let (dit, dah) = if self.config.reversed {
+            (dah_paddle, dit_paddle)
+        } else {
+            (dit_paddle, dah_paddle)
+        };
+
+        // if both paddles are pressed at once then the dit wins
+        if dah && !self.prev_dah {
+            self.last_pressed = Some(Element::Dah);
+        }
+        if dit && !self.prev_dit {
+            self.last_pressed = Some(Element::Dit);
+        }
+
+        let edge = match self.state {
+            State::Idle => self
+                .next_element(dit, dah)
+                .map(|element| self.start_element(element, now)),
+            State::Sending {
+                element,
+                key_down,
+                key_up_at,
+                ends_at,
+            } => {
+                self.latch_memory(element, dit, dah);
+
+                if key_down && now >= key_up_at {
+                    self.state = State::Sending {
+                        element,
+                        key_down: false,
+                        key_up_at,
+                        ends_at,
+                    };
+                    Some(KeyEdge {
+                        key_down: false,
+                        at: key_up_at,
+                    })
+                } else if !key_down && now >= ends_at {
+                    // start the next element straight after the gap, even if
+                    // this update is a little late, so the timing stays exact
+                    self.state = State::Idle;
+                    self.next_element(dit, dah)
+                        .map(|element| self.start_element(element, ends_at))
+                } else {
+                    None
+                }
+            }
+        };
+
+        self.prev_dit = dit;
+        self.prev_dah = dah;
+        edge
+    }
+}
@@ -0,0 +1,390 @@
+//! Keyboard layouts used by the host. The keyboard sends the position of each
+//! key rather than the character printed on it, so the same character needs a
+//! different key (and modifiers) depending on the layout the host is set to.
+//!
+//! Each layout is described by the characters on each key of a standard
+//! keyboard, with and without shift and AltGr, along with its dead keys.
+//! Accented characters that don't have their own key are typed with a dead key
+//! followed by the base letter. Layouts follow the Windows conventions where
+//! platforms differ. The layouts themselves are in the TOML files in
+//! `layouts/`, which build.rs checks and turns into Rust.
+
+/// The modifier bit for the left control key
+pub const CTRL: u8 = 0x01;
+/// The modifier bit for the left shift key
+pub const SHIFT: u8 = 0x02;
+/// The modifier bit for the left alt (Option on macOS) key
+pub const ALT: u8 = 0x04;
+/// The modifier bit for the left GUI (Windows or Command) key
+pub const GUI: u8 = 0x08;
+/// The modifier bit for the right alt (AltGr) key
+pub const ALT_GR: u8 = 0x40;
+
+/// A single key press, with any modifiers held down
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyStroke {
+    /// The modifier bits, e.g. [SHIFT]
+    pub modifier: u8,
+    /// The HID usage ID of the key
+    pub keycode: u8,
+}
+
+impl KeyStroke {
+    pub const fn new(modifier: u8, keycode: u8) -> Self {
+        Self { modifier, keycode }
+    }
+}
+
+/// The HID usage ID for the space bar, which is the same on every layout
+pub(crate) const SPACE: KeyStroke = KeyStroke::new(0, 0x2C);
+
+/// The most key strokes needed for a single character, which is when it is
+/// typed as a Unicode code point
+pub(crate) const MAX_STROKES: usize = 8;
+
+/// The key strokes needed to type a single character
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeySequence {
+    held: u8,
+    strokes: [KeyStroke; MAX_STROKES],
+    len: usize,
+}
+
+impl From<KeyStroke> for KeySequence {
+    fn from(stroke: KeyStroke) -> Self {
+        let mut keys = Self::new(0);
+        keys.push(stroke);
+        keys
+    }
+}
+
+impl KeySequence {
+    /// Creates an empty sequence, with the given modifiers held down until
+    /// the last key is released
+    pub(crate) fn new(held: u8) -> Self {
+        Self {
+            held,
+            strokes: [KeyStroke::new(0, 0); MAX_STROKES],
+            len: 0,
+        }
+    }
+
+    /// Adds a key stroke to the end of the sequence
+    pub(crate) fn push(&mut self, stroke: KeyStroke) {
+        self.strokes[self.len] = stroke;
+        self.len += 1;
+    }
+
+    fn two(first: KeyStroke, second: KeyStroke) -> Self {
+        let mut keys = Self::from(first);
+        keys.push(second);
+        keys
+    }
+
+    /// The modifiers that must stay held down from the first key stroke until
+    /// the last one is released
+    pub fn held(&self) -> u8 {
+        self.held
+    }
+
+    /// Adds modifiers to hold down from the first key stroke until the last
+    /// one is released, e.g. to type a shortcut
+    pub fn hold(&mut self, modifier: u8) {
+        self.held |= modifier;
+    }
+
+    /// Swaps shift on the key strokes that type a letter on the given layout,
+    /// which undoes the host's Caps Lock
+    pub(crate) fn swap_letter_case(&mut self, layout: Layout) {
+        for stroke in &mut self.strokes[..self.len] {
+            let lower = layout.char_for(KeyStroke::new(stroke.modifier & !SHIFT, stroke.keycode));
+            let upper = layout.char_for(KeyStroke::new(stroke.modifier | SHIFT, stroke.keycode));
+            if let (Some(lower), Some(upper)) = (lower, upper) {
+                // Caps Lock leaves keys like ß alone, which shift to something
+                // else entirely
+                if lower != upper && lower.to_uppercase().eq([upper]) {
+                    stroke.modifier ^= SHIFT;
+                }
+            }
+        }
+    }
+
+    /// The key strokes, in the order they should be pressed
+    pub fn strokes(&self) -> &[KeyStroke] {
+        &self.strokes[..self.len]
+    }
+}
+
+/// The accents that can be added by a dead key
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Accent {
+    Acute,
+    Grave,
+    Circumflex,
+    Diaeresis,
+    Tilde,
+}
+
+/// Accented letters and the accent and base letter they are made from
+const COMPOSED: &[(char, Accent, char)] = &[
+    ('á', Accent::Acute, 'a'),
+    ('é', Accent::Acute, 'e'),
+    ('í', Accent::Acute, 'i'),
+    ('ó', Accent::Acute, 'o'),
+    ('ú', Accent::Acute, 'u'),
+    ('ý', Accent::Acute, 'y'),
+    ('à', Accent::Grave, 'a'),
+    ('è', Accent::Grave, 'e'),
+    ('ì', Accent::Grave, 'i'),
+    ('ò', Accent::Grave, 'o'),
+    ('ù', Accent::Grave, 'u'),
+    ('â', Accent::Circumflex, 'a'),
+    ('ê', Accent::Circumflex, 'e'),
+    ('î', Accent::Circumflex, 'i'),
+    ('ô', Accent::Circumflex, 'o'),
+    ('û', Accent::Circumflex, 'u'),
+    ('ä', Accent::Diaeresis, 'a'),
+    ('ë', Accent::Diaeresis, 'e'),
+    ('ï', Accent::Diaeresis, 'i'),
+    ('ö', Accent::Diaeresis, 'o'),
+    ('ü', Accent::Diaeresis, 'u'),
+    ('ÿ', Accent::Diaeresis, 'y'),
+    ('ã', Accent::Tilde, 'a'),
+    ('ñ', Accent::Tilde, 'n'),
+    ('õ', Accent::Tilde, 'o'),
+];
+
+impl Accent {
+    /// The character typed by pressing the dead key followed by space
+    pub fn spacing_char(self) -> char {
+        match self {
+            Accent::Acute => '´',
+            Accent::Grave => '`',
+            Accent::Circumflex => '^',
+            Accent::Diaeresis => '¨',
+            Accent::Tilde => '~',
+        }
+    }
+
+    /// The character typed by pressing the dead key followed by `base`, if
+    /// there is one
+    pub fn compose(self, base: char) -> Option<char> {
+        let lower = to_lowercase(base);
+        COMPOSED
+            .iter()
+            .find(|&&(_, accent, letter)| accent == self && letter == lower)
+            .map(|&(composed, _, _)| {
+                if lower == base {
+                    composed
+                } else {
+                    to_uppercase(composed)
+                }
+            })
+    }
+
+    /// Splits an accented letter into its accent and base letter
+    fn decompose(c: char) -> Option<(Accent, char)> {
+        let lower = to_lowercase(c);
+        COMPOSED
+            .iter()
+            .find(|&&(composed, _, _)| composed == lower)
+            .map(|&(_, accent, base)| {
+                if lower == c {
+                    (accent, base)
+                } else {
+                    (accent, to_uppercase(base))
+                }
+            })
+    }
+}
+
+/// Converts a character to lower case, if it has a single lower case form
+fn to_lowercase(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+/// Converts a character to upper case, if it has a single upper case form
+fn to_uppercase(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) => upper,
+        _ => c,
+    }
+}
+
+/// The characters on each key in [KEYCODES] for one level of a layout. A space
+/// means the key doesn't type anything at this level, or is a dead key.
+type Level = [&'static str; 4];
+
+/// The description of a layout
+struct LayoutTable {
+    base: Level,
+    shift: Level,
+    alt_gr: Level,
+    dead_keys: &'static [(KeyStroke, Accent)],
+}
+
+/// A level with nothing on any key
+const EMPTY: Level = [
+    "             ",
+    "             ",
+    "            ",
+    "           ",
+];
+
+// The key codes and layouts are generated by build.rs from the files in
+// `layouts/`, e.g. `US` from `layouts/us.toml`
+include!(concat!(env!("OUT_DIR"), "/layouts.rs"));
+
+impl LayoutTable {
+    /// The levels of the layout and the modifiers used to reach them
+    fn levels(&self) -> [(u8, &Level); 3] {
+        [
+            (0, &self.base),
+            (SHIFT, &self.shift),
+            (ALT_GR, &self.alt_gr),
+        ]
+    }
+
+    /// Finds the key that types the given character directly
+    fn find(&self, c: char) -> Option<KeyStroke> {
+        if c == ' ' {
+            return Some(SPACE);
+        }
+
+        self.levels().into_iter().find_map(|(modifier, level)| {
+            level
+                .iter()
+                .flat_map(|row| row.chars())
+                .zip(KEYCODES)
+                .find(|&(key, _)| key == c)
+                .map(|(_, keycode)| KeyStroke::new(modifier, keycode))
+        })
+    }
+
+    /// Finds the character typed directly by a key
+    fn char_for(&self, stroke: KeyStroke) -> Option<char> {
+        if stroke == SPACE {
+            return Some(' ');
+        }
+
+        let (_, level) = self
+            .levels()
+            .into_iter()
+            .find(|&(modifier, _)| modifier == stroke.modifier)?;
+        level
+            .iter()
+            .flat_map(|row| row.chars())
+            .zip(KEYCODES)
+            .find(|&(c, keycode)| keycode == stroke.keycode && c != ' ')
+            .map(|(c, _)| c)
+    }
+
+    /// Finds the dead key for an accent
+    fn dead_key(&self, accent: Accent) -> Option<KeyStroke> {
+        self.dead_keys
+            .iter()
+            .find(|&&(_, dead)| dead == accent)
+            .map(|&(stroke, _)| stroke)
+    }
+}
+
+/// A keyboard layout that the host may be set to
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Layout {
+    /// US QWERTY
+    #[default]
+    Us,
+    /// UK QWERTY
+    Uk,
+    /// German QWERTZ
+    De,
+    /// French AZERTY
+    Fr,
+    /// US Dvorak
+    Dvorak,
+    /// Colemak
+    Colemak,
+}
+
+impl Layout {
+    /// Every layout
+    pub const ALL: [Layout; 6] = [
+        Layout::Us,
+        Layout::Uk,
+        Layout::De,
+        Layout::Fr,
+        Layout::Dvorak,
+        Layout::Colemak,
+    ];
+
+    fn table(self) -> &'static LayoutTable {
+        match self {
+            Layout::Us => &US,
+            Layout::Uk => &UK,
+            Layout::De => &DE,
+            Layout::Fr => &FR,
+            Layout::Dvorak => &DVORAK,
+            Layout::Colemak => &COLEMAK,
+        }
+    }
+
+    /// The key strokes that type the given character on this layout, or
+    /// `None` if it can't be typed
+    pub fn keys_for(self, c: char) -> Option<KeySequence> {
+        let table = self.table();
+        if let Some(stroke) = table.find(c) {
+            return Some(stroke.into());
+        }
+
+        // the accent on its own is typed with the dead key and a space
+        if let Some(&(stroke, _)) = table
+            .dead_keys
+            .iter()
+            .find(|&&(_, accent)| accent.spacing_char() == c)
+        {
+            return Some(KeySequence::two(stroke, SPACE));
+        }
+
+        let (accent, base) = Accent::decompose(c)?;
+        Some(KeySequence::two(table.dead_key(accent)?, table.find(base)?))
+    }
+
+    /// The key that types the given character directly on this layout,
+    /// without a dead key
+    pub(crate) fn key_for(self, c: char) -> Option<KeyStroke> {
+        self.table().find(c)
+    }
+
+    /// The character typed directly by a key on this layout, or `None` if it
+    /// doesn't type anything or is a dead key
+    pub fn char_for(self, stroke: KeyStroke) -> Option<char> {
+        self.table().char_for(stroke)
+    }
+
+    /// The accent added by a key if it is a dead key on this layout
+    pub fn dead_key_accent(self, stroke: KeyStroke) -> Option<Accent> {
+        self.table()
+            .dead_keys
+            .iter()
+            .find(|&&(dead, _)| dead == stroke)
+            .map(|&(_, accent)| accent)
+    }
+
+    /// Every character that has its own key on this layout
+    pub fn chars(self) -> impl Iterator<Item = char> {
+        self.table()
+            .levels()
+            .into_iter()
+            .flat_map(|(_, level)| level.iter().flat_map(|row| row.chars()))
+            .filter(|&c| c != ' ')
+    }
+}
@@ -0,0 +1,29 @@
+//! The hardware independent parts of the Morse Kodeboard: decoding and encoding
+//! morse code, debouncing inputs, keying paddles and mapping characters to HID
+//! key codes. Nothing here depends on the firmware, so it can be tested on the
+//! host with `cargo test`.
+
+#![no_std]
+
+// this must come first so the macros are available to the other modules
+mod fmt;
+
+pub mod buffer;
+pub mod code_table;
+pub mod debouncer;
+pub mod decoder;
+pub mod encoder;
+pub mod guess;
+pub mod hid;
+pub mod history;
+pub mod host;
+pub mod key_mapping;
+pub mod keyer;
+pub mod layout;
+pub mod modifiers;
+pub mod speed;
+pub mod time;
+pub mod unicode;
+pub mod user_table;
+
+pub use time::Timestamp;
@@ -0,0 +1,301 @@
+//! The modifier keys (Ctrl, Shift, Alt and GUI) that are held down while
+//! characters are typed, so that shortcuts like Ctrl+C can be sent in morse.
+//!
+//! Each modifier can be picked for just the next character, locked on until it
+//! is picked again, or kept on until the end of the word. Shift kept on until
+//! the end of the word is usually called Caps Word, and locked shift is the
+//! host's Caps Lock, so the two are kept in step.
+//!
+//! Modifiers are picked with a prosign (see
+//! [ProsignAction::Modifier](crate::key_mapping::ProsignAction::Modifier)) or
+//! with gestures on the shift button, which are recognised by [ShiftButton]:
+//!
+//! - a tap picks Shift, the same way as a prosign
+//! - a long press turns on Caps Word
+//! - holding the button while sending `C`, `S`, `A` or `G` picks Ctrl, Shift,
+//!   Alt or GUI instead of typing the letter, see [Chord]
+//! - holding the button while sending `W` keeps the modifiers that were picked
+//!   for the next character on until the end of the word instead, so Ctrl, Alt
+//!   and GUI can be kept on for a word too
+
+use crate::layout::{ALT, CTRL, GUI, SHIFT};
+use crate::time::Timestamp;
+
+/// A modifier key that can be added to what is typed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    /// The Windows key, or Command on macOS
+    Gui,
+}
+
+impl Modifier {
+    /// The number of modifiers
+    pub const COUNT: usize = 4;
+
+    /// Every modifier
+    pub const ALL: [Modifier; Self::COUNT] = [
+        Modifier::Ctrl,
+        Modifier::Shift,
+        Modifier::Alt,
+        Modifier::Gui,
+    ];
+
+    /// The HID modifier bit for the left hand key
+    pub fn bit(self) -> u8 {
+        match self {
+            Modifier::Ctrl => CTRL,
+            Modifier::Shift => SHIFT,
+            Modifier::Alt => ALT,
+            Modifier::Gui => GUI,
+        }
+    }
+
+    /// The modifier picked by sending a letter while the shift button is held,
+    /// if there is one
+    pub fn for_letter(c: char) -> Option<Modifier> {
+        match c.to_ascii_lowercase() {
+            'c' => Some(Modifier::Ctrl),
+            's' => Some(Modifier::Shift),
+            'a' => Some(Modifier::Alt),
+            'g' => Some(Modifier::Gui),
+            _ => None,
+        }
+    }
+}
+
+/// What sending a letter does while the shift button is held
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Chord {
+    /// Picks a modifier, see [Modifiers::pick]
+    Pick(Modifier),
+    /// Keeps the modifiers picked for the next character on until the end of
+    /// the word, see [Modifiers::extend_to_word]
+    Word,
+}
+
+impl Chord {
+    /// The chord for a letter sent while the shift button is held, if the
+    /// letter has one. Any other letter is typed with shift.
+    pub fn for_letter(c: char) -> Option<Chord> {
+        if c.eq_ignore_ascii_case(&'w') {
+            Some(Chord::Word)
+        } else {
+            Modifier::for_letter(c).map(Chord::Pick)
+        }
+    }
+}
+
+/// How long a modifier stays on
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModifierMode {
+    #[default]
+    Off,
+    /// On for the next character or key only
+    OneShot,
+    /// On until it is turned off again
+    Locked,
+    /// On until the end of the current word
+    Word,
+}
+
+/// Something the operator did with the shift button
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// A short press
+    Tap,
+    /// A short press soon after a tap. The tap before it has already been
+    /// reported.
+    DoubleTap,
+    /// A press held down for a while, without sending anything
+    LongPress,
+}
+
+/// The state of every modifier
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modifiers {
+    modes: [ModifierMode; Modifier::COUNT],
+    /// How shift was before the last tap, so a double tap can undo it
+    before_tap: Option<ModifierMode>,
+}
+
+impl Modifiers {
+    /// How long the given modifier stays on
+    pub fn mode(&self, modifier: Modifier) -> ModifierMode {
+        self.modes[modifier as usize]
+    }
+
+    /// Changes how long the given modifier stays on
+    pub fn set(&mut self, modifier: Modifier, mode: ModifierMode) {
+        self.modes[modifier as usize] = mode;
+    }
+
+    /// Picks a modifier, which turns it on for the next character. Picking it
+    /// again locks it on, and a third time turns it off. A modifier that is on
+    /// for the rest of the word is turned off.
+    pub fn pick(&mut self, modifier: Modifier) -> ModifierMode {
+        let mode = match self.mode(modifier) {
+            ModifierMode::Off => ModifierMode::OneShot,
+            ModifierMode::OneShot => ModifierMode::Locked,
+            ModifierMode::Locked | ModifierMode::Word => ModifierMode::Off,
+        };
+        self.set(modifier, mode);
+        mode
+    }
+
+    /// Changes the modifiers for a gesture on the shift button. A tap picks
+    /// shift, a double tap puts shift back how it was before the first tap,
+    /// and a long press turns on Caps Word.
+    pub fn shift_gesture(&mut self, gesture: Gesture) {
+        match gesture {
+            Gesture::Tap => {
+                self.before_tap = Some(self.mode(Modifier::Shift));
+                self.pick(Modifier::Shift);
+            }
+            Gesture::DoubleTap => {
+                if let Some(mode) = self.before_tap.take() {
+                    self.set(Modifier::Shift, mode);
+                }
+            }
+            Gesture::LongPress => self.set(Modifier::Shift, ModifierMode::Word),
+        }
+    }
+
+    /// The HID modifier bits for every modifier that is on. Locked shift isn't
+    /// included, as the host's Caps Lock takes care of it.
+    pub fn active(&self) -> u8 {
+        Modifier::ALL
+            .into_iter()
+            .filter(|&modifier| match self.mode(modifier) {
+                ModifierMode::Off => false,
+                ModifierMode::Locked => modifier != Modifier::Shift,
+                ModifierMode::OneShot | ModifierMode::Word => true,
+            })
+            .fold(0, |bits, modifier| bits | modifier.bit())
+    }
+
+    /// Whether the host's Caps Lock should be on, which is when shift is
+    /// locked
+    pub fn caps_lock(&self) -> bool {
+        self.mode(Modifier::Shift) == ModifierMode::Locked
+    }
+
+    /// Follows a change to the host's Caps Lock, e.g. from another keyboard
+    pub fn sync_caps_lock(&mut self, caps_lock: bool) {
+        if caps_lock {
+            self.set(Modifier::Shift, ModifierMode::Locked);
+        } else if self.caps_lock() {
+            self.set(Modifier::Shift, ModifierMode::Off);
+        }
+    }
+
+    /// The HID modifier bits to add to the next character or key, turning off
+    /// any modifiers that were only on for it
+    pub fn take(&mut self) -> u8 {
+        let bits = self.active();
+        for mode in &mut self.modes {
+            if *mode == ModifierMode::OneShot {
+                *mode = ModifierMode::Off;
+            }
+        }
+        self.before_tap = None;
+        bits
+    }
+
+    /// Keeps every modifier that is on for just the next character on until
+    /// the end of the word instead, e.g. to hold Ctrl for a whole word
+    pub fn extend_to_word(&mut self) {
+        for mode in &mut self.modes {
+            if *mode == ModifierMode::OneShot {
+                *mode = ModifierMode::Word;
+            }
+        }
+        self.before_tap = None;
+    }
+
+    /// Turns off any modifiers that were on until the end of the word
+    pub fn end_word(&mut self) {
+        for mode in &mut self.modes {
+            if *mode == ModifierMode::Word {
+                *mode = ModifierMode::Off;
+            }
+        }
+    }
+}
+
+/// Recognises [Gesture]s on the shift button from its debounced state
+#[derive(Clone, Copy, Debug)]
+pub struct ShiftButton<T: Timestamp> {
+    pressed_at: Option<T>,
+    last_tap: Option<T>,
+    chorded: bool,
+}
+
+impl<T: Timestamp> Default for ShiftButton<T> {
+    fn default() -> Self {
+        Self {
+            pressed_at: None,
+            last_tap: None,
+            chorded: false,
+        }
+    }
+}
+
+impl<T: Timestamp> ShiftButton<T> {
+    /// The longest time between releasing two taps for them to count as a
+    /// double tap
+    pub const DOUBLE_TAP_MS: u64 = 400;
+
+    /// The shortest time the button is held for a long press
+    pub const LONG_PRESS_MS: u64 = 600;
+
+    /// Whether the button is held down
+    pub fn is_held(&self) -> bool {
+        self.pressed_at.is_some()
+    }
+
+    /// Marks the current press as part of a chord, e.g. picking a modifier by
+    /// sending a letter, so it isn't reported as a gesture when released
+    pub fn chord(&mut self) {
+        self.chorded = self.is_held();
+    }
+
+    /// Updates the state of the button, returning a gesture when it is
+    /// released. Gestures are reported on release so that a chord or long
+    /// press isn't mistaken for a tap.
+    pub fn update(&mut self, pressed: bool, now: T) -> Option<Gesture> {
+        match (pressed, self.pressed_at) {
+            (true, None) => {
+                self.pressed_at = Some(now);
+                None
+            }
+            (false, Some(pressed_at)) => {
+                self.pressed_at = None;
+                if core::mem::take(&mut self.chorded) {
+                    self.last_tap = None;
+                    None
+                } else if now.millis_since(pressed_at) >= Self::LONG_PRESS_MS {
+                    self.last_tap = None;
+                    Some(Gesture::LongPress)
+                } else if self
+                    .last_tap
+                    .is_some_and(|last| now.millis_since(last) <= Self::DOUBLE_TAP_MS)
+                {
+                    self.last_tap = None;
+                    Some(Gesture::DoubleTap)
+                } else {
+                    self.last_tap = Some(now);
+                    Some(Gesture::Tap)
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Tracks how fast the operator is sending by keeping a running estimate of
+//! the length of their dits and their dahs. The most recent key presses are
+//! split into two groups (short and long) and the average of each group is
+//! used as the dit and dah length.
+
+use crate::code_table::Element;
+
+/// The number of recent key presses used to estimate the speed
+const WINDOW: usize = 12;
+
+/// The number of rounds used to split the recent key presses into two groups
+const ROUNDS: usize = 4;
+
+/// The fastest speed that will be tracked, about 60 WPM
+const MIN_DIT_MS: f32 = 20.0;
+/// The slowest speed that will be tracked, about 3 WPM
+const MAX_DIT_MS: f32 = 400.0;
+
+/// The allowed range for the ratio of dah length to dit length. If the two
+/// groups are closer together than this then the recent key presses were all
+/// dits (or all dahs) that just vary a bit in length.
+const MIN_RATIO: f32 = 2.0;
+const MAX_RATIO: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpeedTracker {
+    /// The estimated length of a dit
+    dit_ms: f32,
+    /// The estimated length of a dah
+    dah_ms: f32,
+    /// The most recent key press lengths, used as a ring buffer
+    recent: [u16; WINDOW],
+    /// The number of values in `recent`
+    count: usize,
+    /// The index in `recent` to write the next key press length to
+    next: usize,
+}
+
+impl SpeedTracker {
+    /// Creates a tracker that assumes the operator starts at the given speed
+    pub fn new(dit_ms: u64) -> Self {
+        let dit_ms = (dit_ms as f32).clamp(MIN_DIT_MS, MAX_DIT_MS);
+        Self {
+            dit_ms,
+            dah_ms: dit_ms * 3.0,
+            recent: [0; WINDOW],
+            count: 0,
+            next: 0,
+        }
+    }
+
+    /// The key press length that separates dits from dahs
+    pub fn dah_threshold_ms(&self) -> f32 {
+        (self.dit_ms + self.dah_ms) / 2.0
+    }
+
+    /// The estimated length of one unit, combining both groups. This is used
+    /// to measure the gaps between elements, letters and words.
+    pub fn unit_ms(&self) -> f32 {
+        (self.dit_ms + self.dah_ms / 3.0) / 2.0
+    }
+
+    /// Records the length of a key press, updates the speed estimate and then
+    /// decides whether the key press was a dit or a dah
+    pub fn observe(&mut self, duration_ms: u64) -> Element {
+        self.recent[self.next] = duration_ms.min(u16::MAX as u64) as u16;
+        self.next = (self.next + 1) % WINDOW;
+        self.count = (self.count + 1).min(WINDOW);
+
+        self.update_estimates();
+
+        if (duration_ms as f32) < self.dah_threshold_ms() {
+            Element::Dit
+        } else {
+            Element::Dah
+        }
+    }
+}
+
+/// Private methods
+impl SpeedTracker {
+    /// Splits the recent key presses into short and long groups, and uses the
+    /// average of each group as the new dit and dah lengths
+    fn update_estimates(&mut self) {
+        let recent = &self.recent[..self.count];
+
+        let mut short = *recent.iter().min().unwrap_or(&0) as f32;
+        let mut long = *recent.iter().max().unwrap_or(&0) as f32;
+        for _ in 0..ROUNDS {
+            let threshold = (short + long) / 2.0;
+            short = mean(recent.iter().filter(|d| (**d as f32) < threshold)).unwrap_or(short);
+            long = mean(recent.iter().filter(|d| (**d as f32) >= threshold)).unwrap_or(long);
+        }
+
+        let ratio = self.dah_ms / self.dit_ms;
+        if long >= short * MIN_RATIO {
+            self.dit_ms = short;
+            self.dah_ms = long.min(short * MAX_RATIO);
+        } else {
+            // the recent key presses are all the same element, so work out
+            // which from the current estimate and keep the same ratio
+            let average = mean(recent.iter()).unwrap_or(self.dit_ms);
+            if average < self.dah_threshold_ms() {
+                self.dit_ms = average;
+                self.dah_ms = average * ratio;
+            } else {
+                self.dah_ms = average;
+                self.dit_ms = average / ratio;
+            }
+        }
+
+        let dit_ms = self.dit_ms.clamp(MIN_DIT_MS, MAX_DIT_MS);
+        self.dah_ms *= dit_ms / self.dit_ms;
+        self.dit_ms = dit_ms;
+    }
+}
+
+/// The average of the given key press lengths, or `None` if there are none
+fn mean<'a>(values: impl Iterator<Item = &'a u16>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), d| (sum + *d as f32, count + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f32)
+    }
+}
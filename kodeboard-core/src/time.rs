@@ -0,0 +1,44 @@
+//! The time source used by the decoder and keyer. These are driven entirely
+//! by the timestamps passed to them, so they can run against the firmware's
+//! clock or against made up times in tests.
+
+/// A point in time, measured in milliseconds from an arbitrary starting point
+pub trait Timestamp: Copy + Ord {
+    /// The number of milliseconds since the starting point
+    fn as_millis(&self) -> u64;
+
+    /// The timestamp the given number of milliseconds after the starting point
+    fn from_millis(millis: u64) -> Self;
+
+    /// The number of milliseconds between an earlier timestamp and this one
+    fn millis_since(&self, earlier: Self) -> u64 {
+        self.as_millis().saturating_sub(earlier.as_millis())
+    }
+
+    /// The timestamp the given number of milliseconds after this one
+    fn add_millis(&self, millis: u64) -> Self {
+        Self::from_millis(self.as_millis() + millis)
+    }
+}
+
+/// Plain milliseconds, mostly useful for tests
+impl Timestamp for u64 {
+    fn as_millis(&self) -> u64 {
+        *self
+    }
+
+    fn from_millis(millis: u64) -> Self {
+        millis
+    }
+}
+
+#[cfg(feature = "embassy-time")]
+impl Timestamp for embassy_time::Instant {
+    fn as_millis(&self) -> u64 {
+        embassy_time::Instant::as_millis(self)
+    }
+
+    fn from_millis(millis: u64) -> Self {
+        embassy_time::Instant::from_millis(millis)
+    }
+}
@@ -0,0 +1,122 @@
+//! Types characters that aren't on the host's keyboard layout by entering their
+//! Unicode code point with the host's input method. Each operating system has
+//! its own way of doing this, and some need to be turned on first.
+
+use crate::layout::{ALT, CTRL, KeySequence, KeyStroke, Layout, SHIFT, SPACE};
+
+/// The HID usage ID of the plus key on the numeric keypad
+const KEYPAD_PLUS: KeyStroke = KeyStroke::new(0, 0x57);
+
+/// The key for a digit on the numeric keypad, which is the same on every
+/// layout
+fn keypad_digit(digit: u32) -> KeyStroke {
+    match digit {
+        0 => KeyStroke::new(0, 0x62),
+        // the keypad keys run from 1 to 9
+        _ => KeyStroke::new(0, 0x58 + digit as u8),
+    }
+}
+
+/// A way of entering Unicode code points on the host
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnicodeEntry {
+    /// Ctrl+Shift+U, the code point in hex and then space, as supported by
+    /// IBus and GTK applications on Linux
+    Linux,
+    /// The code point in decimal on the numeric keypad while Alt is held, on
+    /// Windows. Code points below 256 get a leading zero so they come from
+    /// Windows-1252, which matches Unicode for the accented Latin letters.
+    /// Anything above that only works in applications that accept Unicode alt
+    /// codes.
+    WindowsAltCode,
+    /// Alt held down while typing plus on the numeric keypad and then the code
+    /// point in hex, on Windows. This needs `EnableHexNumpad` to be set in the
+    /// registry.
+    WindowsHexNumpad,
+    /// Each UTF-16 code unit as four hex digits while Option is held, using the
+    /// "Unicode Hex Input" input source on macOS. The input source has US key
+    /// positions, whatever the usual layout is.
+    MacOs,
+}
+
+impl UnicodeEntry {
+    /// Every way of entering code points
+    pub const ALL: [UnicodeEntry; 4] = [
+        UnicodeEntry::Linux,
+        UnicodeEntry::WindowsAltCode,
+        UnicodeEntry::WindowsHexNumpad,
+        UnicodeEntry::MacOs,
+    ];
+
+    /// The key strokes that enter the given character on a host with the given
+    /// layout, or `None` if the layout is missing a key that is needed
+    pub fn keys_for(self, c: char, layout: Layout) -> Option<KeySequence> {
+        let code_point = c as u32;
+        match self {
+            UnicodeEntry::Linux => {
+                let u = layout.key_for('u')?;
+                let mut keys = KeySequence::new(0);
+                keys.push(KeyStroke::new(CTRL | SHIFT, u.keycode));
+                push_hex(&mut keys, code_point, 1, |digit| hex_key(digit, layout))?;
+                keys.push(SPACE);
+                Some(keys)
+            }
+            UnicodeEntry::WindowsAltCode => {
+                let mut keys = KeySequence::new(ALT);
+                if code_point < 256 {
+                    keys.push(keypad_digit(0));
+                }
+                let mut digits = 1;
+                while code_point / 10u32.pow(digits) > 0 {
+                    digits += 1;
+                }
+                for place in (0..digits).rev() {
+                    keys.push(keypad_digit(code_point / 10u32.pow(place) % 10));
+                }
+                Some(keys)
+            }
+            UnicodeEntry::WindowsHexNumpad => {
+                let mut keys = KeySequence::new(ALT);
+                keys.push(KEYPAD_PLUS);
+                push_hex(&mut keys, code_point, 1, |digit| match digit {
+                    0..=9 => Some(keypad_digit(digit)),
+                    _ => hex_key(digit, layout),
+                })?;
+                Some(keys)
+            }
+            UnicodeEntry::MacOs => {
+                let mut keys = KeySequence::new(ALT);
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    push_hex(&mut keys, *unit as u32, 4, |digit| {
+                        hex_key(digit, Layout::Us)
+                    })?;
+                }
+                Some(keys)
+            }
+        }
+    }
+}
+
+/// The key that types a hex digit on the given layout
+fn hex_key(digit: u32, layout: Layout) -> Option<KeyStroke> {
+    layout.key_for(char::from_digit(digit, 16)?)
+}
+
+/// Adds the keys for a number in hex, most significant digit first, padded
+/// with zeros to at least `min_digits`
+fn push_hex(
+    keys: &mut KeySequence,
+    value: u32,
+    min_digits: u32,
+    key_for_digit: impl Fn(u32) -> Option<KeyStroke>,
+) -> Option<()> {
+    let mut digits = min_digits;
+    while value >> (4 * digits) > 0 {
+        digits += 1;
+    }
+    for place in (0..digits).rev() {
+        keys.push(key_for_digit((value >> (4 * place)) & 0xF)?);
+    }
+    Some(())
+}
@@ -0,0 +1,507 @@
+//! Code tables defined by the operator, which map their own sequences to a
+//! string of text, a key combination or a macro. These are checked before the
+//! built-in [CodeTable](crate::code_table::CodeTable), so they can also replace
+//! a built-in code.
+//!
+//! A table is kept as bytes so that it can be stored in flash and replaced over
+//! USB without rebuilding the firmware. It is checked as it is parsed, so a
+//! corrupt or ambiguous table is rejected rather than typing the wrong thing.
+//! Everything is little endian:
+//!
+//! | Bytes       | Contents                                      |
+//! |-------------|-----------------------------------------------|
+//! | 0..4        | `KBUT`                                        |
+//! | 4           | The format version, currently 1               |
+//! | 5           | The number of entries                         |
+//! | 6..8        | The length of the entries in bytes            |
+//! | 8..12       | The CRC-32 of the entries                     |
+//! | 12..        | The entries                                   |
+//!
+//! Each entry is the length of the code, the code written with `.` and `-` (see
+//! [Element](crate::code_table::Element)), the kind of action, the length of
+//! the action and then the action itself:
+//!
+//! - `0`, text: the text as UTF-8
+//! - `1`, a key: the modifiers and the HID key code
+//! - `2`, a macro: a list of steps, each of which is `0`, the length of the
+//!   text and the text as UTF-8, `1`, the modifiers and the key code, or `2` and
+//!   a delay in milliseconds as a `u16`
+
+use crate::code_table::{Element, MAX_CODE_LEN, MorseCode};
+use crate::layout::KeyStroke;
+
+/// The longest table that can be stored, which fits in one flash sector
+pub const MAX_TABLE_LEN: usize = 4096;
+
+/// The most entries a table can have
+pub const MAX_ENTRIES: usize = 64;
+
+/// The start of every table
+const MAGIC: [u8; 4] = *b"KBUT";
+
+/// The version of the format, which is increased if it ever changes
+const VERSION: u8 = 1;
+
+/// The length of the header before the entries
+const HEADER_LEN: usize = 12;
+
+// The kinds of actions and macro steps
+const TEXT: u8 = 0;
+const KEY: u8 = 1;
+const MACRO: u8 = 2;
+const DELAY: u8 = 2;
+
+/// The reasons a table can't be used
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TableError {
+    /// The table doesn't start with the right header, e.g. because nothing has
+    /// been stored yet, or it was written for a different version
+    BadHeader,
+    /// The table is longer than [MAX_TABLE_LEN] or has more than
+    /// [MAX_ENTRIES] entries, or doesn't fit in the buffer it's written to
+    TooBig,
+    /// The table is shorter than the header says
+    Truncated,
+    /// The entries don't match their checksum, e.g. because an upload was cut
+    /// short
+    BadChecksum,
+    /// The entry at this index can't be read, or it is the number of entries if
+    /// there is something left over after the last one
+    Malformed(usize),
+    /// Two entries have the same code
+    DuplicateCode { first: usize, second: usize },
+    /// The code of one entry is the start of another entry's code. These are
+    /// separated by a letter gap, but user codes tend to be long and an
+    /// operator who hesitates part way through the longer one would get the
+    /// shorter one instead, so it is almost always a mistake in the table.
+    PrefixConflict { prefix: usize, code: usize },
+}
+
+/// A single step of a macro
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MacroStep<'a> {
+    /// Types some text
+    Text(&'a str),
+    /// Presses and releases a key along with its modifiers
+    Key(KeyStroke),
+    /// Waits for the given number of milliseconds, e.g. for an application to
+    /// open
+    Delay(u16),
+}
+
+/// The steps of a macro, which are read from the table as they are iterated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Macro<'a> {
+    steps: &'a [u8],
+}
+
+impl<'a> Iterator for Macro<'a> {
+    type Item = MacroStep<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut reader = Reader { bytes: self.steps };
+        // the steps were all checked when the table was parsed
+        let step = read_step(&mut reader)?;
+        self.steps = reader.bytes;
+        Some(step)
+    }
+}
+
+/// What to do when the operator sends a user code
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UserAction<'a> {
+    /// Types some text, e.g. a name or an address
+    Text(&'a str),
+    /// Presses a key along with its modifiers, e.g. Ctrl+C
+    Key(KeyStroke),
+    /// Performs each step in turn
+    Macro(Macro<'a>),
+}
+
+/// A code and the action it performs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UserEntry<'a> {
+    pub code: MorseCode,
+    pub action: UserAction<'a>,
+}
+
+/// The codes in a table, which the [Decoder](crate::decoder::Decoder) keeps
+/// so it knows which sequences to leave to the table. This is owned, so the
+/// decoder doesn't have to borrow the table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UserCodes {
+    codes: [MorseCode; MAX_ENTRIES],
+    len: usize,
+}
+
+impl Default for UserCodes {
+    fn default() -> Self {
+        Self {
+            codes: [MorseCode::new(); MAX_ENTRIES],
+            len: 0,
+        }
+    }
+}
+
+impl UserCodes {
+    pub fn as_slice(&self) -> &[MorseCode] {
+        &self.codes[..self.len]
+    }
+
+    /// Whether the code has an entry in the table
+    pub fn contains(&self, code: &MorseCode) -> bool {
+        self.as_slice().contains(code)
+    }
+
+    fn push(&mut self, code: MorseCode) {
+        if self.len < MAX_ENTRIES {
+            self.codes[self.len] = code;
+            self.len += 1;
+        }
+    }
+
+    /// Finds the first pair of codes that can't both be in a table
+    fn check_conflicts(&self) -> Result<(), TableError> {
+        let codes = self.as_slice();
+        for (second, code) in codes.iter().enumerate() {
+            for (first, other) in codes[..second].iter().enumerate() {
+                if code == other {
+                    return Err(TableError::DuplicateCode { first, second });
+                } else if code.elements().starts_with(other.elements()) {
+                    return Err(TableError::PrefixConflict {
+                        prefix: first,
+                        code: second,
+                    });
+                } else if other.elements().starts_with(code.elements()) {
+                    return Err(TableError::PrefixConflict {
+                        prefix: second,
+                        code: first,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A table that has been checked, borrowing the bytes it was parsed from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UserTable<'a> {
+    bytes: &'a [u8],
+    count: usize,
+}
+
+impl<'a> UserTable<'a> {
+    /// Checks and parses a table. Anything after the end of the table is
+    /// ignored, so this can be given the whole of the flash sector it is
+    /// stored in.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, TableError> {
+        let mut reader = Reader { bytes };
+        let header = reader.take(HEADER_LEN).ok_or(TableError::BadHeader)?;
+        if header[..4] != MAGIC || header[4] != VERSION {
+            return Err(TableError::BadHeader);
+        }
+        let count = header[5] as usize;
+        let len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let checksum = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if count > MAX_ENTRIES || HEADER_LEN + len > MAX_TABLE_LEN {
+            return Err(TableError::TooBig);
+        }
+
+        let entries = reader.take(len).ok_or(TableError::Truncated)?;
+        if crc32(entries) != checksum {
+            return Err(TableError::BadChecksum);
+        }
+
+        let mut reader = Reader { bytes: entries };
+        let mut codes = UserCodes::default();
+        for index in 0..count {
+            let entry = read_entry(&mut reader).ok_or(TableError::Malformed(index))?;
+            codes.push(entry.code);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(TableError::Malformed(count));
+        }
+        codes.check_conflicts()?;
+
+        Ok(Self {
+            bytes: &bytes[..HEADER_LEN + len],
+            count,
+        })
+    }
+
+    /// The table as it is stored, without anything that came after it
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Every entry, in the order they are stored
+    pub fn entries(&self) -> impl Iterator<Item = UserEntry<'a>> + use<'a> {
+        let mut reader = Reader {
+            bytes: &self.bytes[HEADER_LEN..],
+        };
+        // the entries were all checked when the table was parsed
+        core::iter::from_fn(move || read_entry(&mut reader))
+    }
+
+    /// The action for a code, or `None` if it isn't in the table
+    pub fn lookup(&self, code: &MorseCode) -> Option<UserAction<'a>> {
+        self.entries()
+            .find(|entry| entry.code == *code)
+            .map(|entry| entry.action)
+    }
+
+    /// Every code in the table
+    pub fn codes(&self) -> UserCodes {
+        let mut codes = UserCodes::default();
+        for entry in self.entries() {
+            codes.push(entry.code);
+        }
+        codes
+    }
+}
+
+/// Writes a table into a buffer, e.g. so that it can be uploaded
+pub struct TableWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    count: usize,
+}
+
+impl<'a> TableWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: HEADER_LEN,
+            count: 0,
+        }
+    }
+
+    /// Adds an entry that types some text
+    pub fn push_text(&mut self, code: &MorseCode, text: &str) -> Result<(), TableError> {
+        self.push(code, TEXT, text.len(), |writer| {
+            writer.write(text.as_bytes())
+        })
+    }
+
+    /// Adds an entry that presses a key
+    pub fn push_key(&mut self, code: &MorseCode, key: KeyStroke) -> Result<(), TableError> {
+        self.push(code, KEY, 2, |writer| {
+            writer.write(&[key.modifier, key.keycode])
+        })
+    }
+
+    /// Adds an entry that runs a macro
+    pub fn push_macro(&mut self, code: &MorseCode, steps: &[MacroStep]) -> Result<(), TableError> {
+        let mut len = 0;
+        for step in steps {
+            len += match step {
+                MacroStep::Text(text) if text.len() > u8::MAX as usize => {
+                    return Err(TableError::Malformed(self.count));
+                }
+                MacroStep::Text(text) => 2 + text.len(),
+                MacroStep::Key(_) | MacroStep::Delay(_) => 3,
+            };
+        }
+
+        self.push(code, MACRO, len, |writer| {
+            for step in steps {
+                match step {
+                    MacroStep::Text(text) => {
+                        writer.write(&[TEXT, text.len() as u8])?;
+                        writer.write(text.as_bytes())?;
+                    }
+                    MacroStep::Key(key) => writer.write(&[KEY, key.modifier, key.keycode])?,
+                    MacroStep::Delay(ms) => {
+                        writer.write(&[DELAY])?;
+                        writer.write(&ms.to_le_bytes())?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes the header and checks the finished table
+    pub fn finish(self) -> Result<UserTable<'a>, TableError> {
+        if self.buf.len() < HEADER_LEN {
+            return Err(TableError::TooBig);
+        }
+
+        let entries_len = (self.len - HEADER_LEN) as u16;
+        let checksum = crc32(&self.buf[HEADER_LEN..self.len]);
+        self.buf[..4].copy_from_slice(&MAGIC);
+        self.buf[4] = VERSION;
+        self.buf[5] = self.count as u8;
+        self.buf[6..8].copy_from_slice(&entries_len.to_le_bytes());
+        self.buf[8..12].copy_from_slice(&checksum.to_le_bytes());
+
+        let buf: &'a [u8] = self.buf;
+        UserTable::parse(&buf[..self.len])
+    }
+
+    /// Adds an entry, leaving the table as it was if it doesn't fit
+    fn push(
+        &mut self,
+        code: &MorseCode,
+        kind: u8,
+        len: usize,
+        write_action: impl FnOnce(&mut Self) -> Result<(), TableError>,
+    ) -> Result<(), TableError> {
+        if self.count == MAX_ENTRIES {
+            return Err(TableError::TooBig);
+        }
+        if code.is_empty() || len > u8::MAX as usize {
+            return Err(TableError::Malformed(self.count));
+        }
+
+        let start = self.len;
+        let result = self.write_entry(code, kind, len, write_action);
+        match result {
+            Ok(()) => self.count += 1,
+            Err(_) => self.len = start,
+        }
+        result
+    }
+
+    fn write_entry(
+        &mut self,
+        code: &MorseCode,
+        kind: u8,
+        len: usize,
+        write_action: impl FnOnce(&mut Self) -> Result<(), TableError>,
+    ) -> Result<(), TableError> {
+        self.write(&[code.len() as u8])?;
+        for element in code.elements() {
+            self.write(&[element.as_char() as u8])?;
+        }
+        self.write(&[kind, len as u8])?;
+        write_action(self)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), TableError> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len().min(MAX_TABLE_LEN) {
+            return Err(TableError::TooBig);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Reads the table from the front, returning `None` if it runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn str(&mut self, len: usize) -> Option<&'a str> {
+        core::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn key(&mut self) -> Option<KeyStroke> {
+        Some(KeyStroke::new(self.u8()?, self.u8()?))
+    }
+}
+
+/// Reads and checks a single entry
+fn read_entry<'a>(reader: &mut Reader<'a>) -> Option<UserEntry<'a>> {
+    let code_len = reader.u8()? as usize;
+    if code_len == 0 || code_len > MAX_CODE_LEN {
+        return None;
+    }
+    let mut code = MorseCode::new();
+    for &c in reader.take(code_len)? {
+        code.push(Element::try_from_ascii(c)?);
+    }
+
+    let kind = reader.u8()?;
+    let len = reader.u8()? as usize;
+    let mut action = Reader {
+        bytes: reader.take(len)?,
+    };
+    let parsed = match kind {
+        TEXT => UserAction::Text(action.str(len)?),
+        KEY => UserAction::Key(action.key()?),
+        MACRO => {
+            let steps = action.bytes;
+            while !action.bytes.is_empty() {
+                read_step(&mut action)?;
+            }
+            UserAction::Macro(Macro { steps })
+        }
+        _ => return None,
+    };
+    if !action.bytes.is_empty() {
+        return None;
+    }
+
+    Some(UserEntry {
+        code,
+        action: parsed,
+    })
+}
+
+/// Reads and checks a single macro step
+fn read_step<'a>(reader: &mut Reader<'a>) -> Option<MacroStep<'a>> {
+    match reader.u8()? {
+        TEXT => {
+            let len = reader.u8()? as usize;
+            Some(MacroStep::Text(reader.str(len)?))
+        }
+        KEY => Some(MacroStep::Key(reader.key()?)),
+        DELAY => Some(MacroStep::Delay(reader.u16()?)),
+        _ => None,
+    }
+}
+
+/// The CRC-32 used by zip and Ethernet. This is worked out a bit at a time as
+/// tables are small and only checked when they are loaded.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
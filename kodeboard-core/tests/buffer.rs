@@ -0,0 +1,71 @@
+use kodeboard_core::buffer::{EventBuffer, OverflowPolicy};
+
+fn drain<const N: usize>(buffer: &mut EventBuffer<u32, N>) -> Vec<u32> {
+    std::iter::from_fn(|| buffer.pop()).collect()
+}
+
+#[test]
+fn returns_events_in_order() {
+    let mut buffer = EventBuffer::<u32, 4>::default();
+    assert!(buffer.is_empty());
+    assert!(buffer.push(1));
+    assert!(buffer.push(2));
+    assert!(buffer.push(3));
+    assert_eq!(buffer.len(), 3);
+    assert_eq!(drain(&mut buffer), [1, 2, 3]);
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test]
+fn wraps_around() {
+    let mut buffer = EventBuffer::<u32, 3>::default();
+    for i in 0..10 {
+        assert!(buffer.push(i));
+        assert!(buffer.push(i + 100));
+        assert_eq!(drain(&mut buffer), [i, i + 100]);
+    }
+    assert_eq!(buffer.overflows(), 0);
+}
+
+#[test]
+fn drops_new_events_when_full() {
+    let mut buffer = EventBuffer::<u32, 3>::new(OverflowPolicy::DropNewest);
+    for i in 1..=5 {
+        buffer.push(i);
+    }
+    assert_eq!(buffer.overflows(), 2);
+    assert_eq!(drain(&mut buffer), [1, 2, 3]);
+}
+
+#[test]
+fn drops_old_events_when_full() {
+    let mut buffer = EventBuffer::<u32, 3>::new(OverflowPolicy::DropOldest);
+    for i in 1..=5 {
+        buffer.push(i);
+    }
+    assert_eq!(buffer.overflows(), 2);
+    assert_eq!(drain(&mut buffer), [3, 4, 5]);
+}
+
+#[test]
+fn reports_dropped_events() {
+    let mut buffer = EventBuffer::<u32, 1>::default();
+    assert!(buffer.push(1));
+    assert!(!buffer.push(2));
+
+    buffer.policy = OverflowPolicy::DropOldest;
+    assert!(!buffer.push(3));
+    assert_eq!(buffer.pop(), Some(3));
+    assert_eq!(buffer.overflows(), 2);
+}
+
+#[test]
+fn keeps_counting_after_emptying() {
+    let mut buffer = EventBuffer::<u32, 1>::default();
+    buffer.push(1);
+    buffer.push(2);
+    drain(&mut buffer);
+    buffer.push(3);
+    buffer.push(4);
+    assert_eq!(buffer.overflows(), 2);
+}
@@ -0,0 +1,70 @@
+//! The buttons are pulled up, so the firmware passes the pin level to the
+//! debouncer and a low pin (a pressed button) reads as "on".
+
+use kodeboard_core::debouncer::DebouncedInput;
+
+const PRESSED: bool = false;
+const RELEASED: bool = true;
+
+/// Feeds the same level to the debouncer a number of times, returning the
+/// debounced state after each sample
+fn hold(input: &mut DebouncedInput, level: bool, samples: usize) -> Vec<bool> {
+    (0..samples).map(|_| input.debounce(level)).collect()
+}
+
+#[test]
+fn starts_in_the_given_state() {
+    assert!(DebouncedInput::new(true).current());
+    assert!(!DebouncedInput::new(false).current());
+}
+
+#[test]
+fn changes_after_sixteen_steady_samples() {
+    let mut input = DebouncedInput::new(false);
+
+    let states = hold(&mut input, PRESSED, 16);
+    assert!(states[..15].iter().all(|&on| !on));
+    assert!(states[15]);
+    assert!(input.current());
+
+    let states = hold(&mut input, RELEASED, 16);
+    assert!(states[..15].iter().all(|&on| on));
+    assert!(!states[15]);
+    assert!(!input.current());
+}
+
+#[test]
+fn ignores_bounces_while_pressed() {
+    let mut input = DebouncedInput::new(false);
+    hold(&mut input, PRESSED, 16);
+
+    // bounces keep the state the input settled in, not the one it started in
+    for _ in 0..10 {
+        assert!(input.debounce(RELEASED));
+        assert!(input.debounce(PRESSED));
+    }
+    assert!(input.current());
+}
+
+#[test]
+fn ignores_bounces_while_released() {
+    let mut input = DebouncedInput::new(true);
+    hold(&mut input, RELEASED, 16);
+
+    for _ in 0..10 {
+        assert!(!input.debounce(PRESSED));
+        assert!(!input.debounce(RELEASED));
+    }
+    assert!(!input.current());
+}
+
+#[test]
+fn a_bounce_restarts_the_count() {
+    let mut input = DebouncedInput::new(false);
+    hold(&mut input, PRESSED, 15);
+    input.debounce(RELEASED);
+
+    let states = hold(&mut input, PRESSED, 16);
+    assert!(states[..15].iter().all(|&on| !on));
+    assert!(states[15]);
+}
@@ -0,0 +1,660 @@
+use kodeboard_core::code_table::{CodeTable, Element, MorseCode, Prosign};
+use kodeboard_core::decoder::{DecodeError, Decoder, DecoderEvent, ErrorPolicy, Timing};
+
+const PROSIGNS: &[(&str, Prosign)] = &[
+    (".-.-.", Prosign::AR),
+    ("...-.-", Prosign::SK),
+    ("-...-", Prosign::BT),
+    ("-.--.", Prosign::KN),
+    ("...-.", Prosign::SN),
+    ("........", Prosign::HH),
+    ("-.-.-", Prosign::CT),
+    ("-..---", Prosign::DO),
+];
+
+/// Converts dits and dahs into perfectly timed key presses. Letters are
+/// separated by spaces and words by ` / `. The gaps between letters and words
+/// use `spacing_ms` as their unit, which is the same as `dit_ms` unless
+/// Farnsworth timing is used.
+fn keying(code: &str, dit_ms: u64, spacing_ms: u64) -> Vec<(bool, u64)> {
+    let mut signal = Vec::new();
+    for (i, word) in code.split(" / ").enumerate() {
+        if i > 0 {
+            signal.push((false, 7 * spacing_ms));
+        }
+        for (j, letter) in word.split(' ').enumerate() {
+            if j > 0 {
+                signal.push((false, 3 * spacing_ms));
+            }
+            for (k, element) in letter.chars().enumerate() {
+                if k > 0 {
+                    signal.push((false, dit_ms));
+                }
+                let units = if element == '.' { 1 } else { 3 };
+                signal.push((true, units * dit_ms));
+            }
+        }
+    }
+
+    // leave the key up long enough to finish the last word
+    signal.push((false, 10 * spacing_ms));
+    signal
+}
+
+/// Feeds the signal to a decoder once per millisecond, like the firmware does
+fn decode(timing: Timing, signal: &[(bool, u64)]) -> Vec<DecoderEvent> {
+    decode_with(&mut Decoder::new(timing, 0), signal)
+}
+
+/// Feeds the signal to a decoder that was created at time zero
+fn decode_with(decoder: &mut Decoder<u64>, signal: &[(bool, u64)]) -> Vec<DecoderEvent> {
+    let mut now = 0;
+    let mut events = Vec::new();
+
+    for &(key_down, duration_ms) in signal {
+        for _ in 0..duration_ms {
+            now += 1;
+            events.extend(decoder.push(key_down, now));
+        }
+    }
+
+    events
+}
+
+/// Removes the individual elements, leaving the characters, gaps and errors
+fn characters(events: &[DecoderEvent]) -> Vec<DecoderEvent> {
+    events
+        .iter()
+        .copied()
+        .filter(|event| !matches!(event, DecoderEvent::Element { .. }))
+        .collect()
+}
+
+/// Writes out the decoded characters, with prosigns in angle brackets and `#`
+/// for anything that couldn't be decoded
+fn text(events: &[DecoderEvent]) -> String {
+    events
+        .iter()
+        .map(|event| match event {
+            DecoderEvent::Element { .. } => String::new(),
+            DecoderEvent::Character(c) => c.to_string(),
+            DecoderEvent::Text(text) => text.to_string(),
+            DecoderEvent::Prosign(prosign) => format!("<{prosign:?}>"),
+            DecoderEvent::WordGap => " ".to_string(),
+            DecoderEvent::ShortError => "<short error>".to_string(),
+            DecoderEvent::Error(_) => "#".to_string(),
+            DecoderEvent::Pattern(code) => code
+                .elements()
+                .iter()
+                .map(|element| element.as_char())
+                .collect(),
+            DecoderEvent::UserCode(_) => "<user>".to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn decodes_every_prosign() {
+    for &(code, prosign) in PROSIGNS {
+        let events = decode(Timing::new(60), &keying(code, 60, 60));
+        assert_eq!(
+            characters(&events),
+            [DecoderEvent::Prosign(prosign)],
+            "{code}"
+        );
+    }
+}
+
+#[test]
+fn splits_letters_and_words_on_gaps() {
+    let signal = keying("-- --- .-. ... . / -.- . -.--", 60, 60);
+    assert_eq!(text(&decode(Timing::new(60), &signal)), "morse key ");
+}
+
+#[test]
+fn runs_letters_together_without_a_letter_gap() {
+    // "e" and "t" sent with only an element gap between them are "a"
+    let signal = keying(".-", 60, 60);
+    assert_eq!(text(&decode(Timing::new(60), &signal)), "a ");
+}
+
+#[test]
+fn tolerates_uneven_keying() {
+    // dits and dahs a little long or short, and gaps a little off
+    let signal = [
+        (true, 80),
+        (false, 50),
+        (true, 150),
+        (false, 200),
+        (true, 200),
+        (false, 70),
+        (true, 45),
+        (false, 1000),
+    ];
+    assert_eq!(text(&decode(Timing::new(60), &signal)), "an ");
+}
+
+#[test]
+fn reports_unknown_sequences() {
+    let events = decode(Timing::new(60), &keying("..-- .-", 60, 60));
+    assert_eq!(
+        characters(&events),
+        [
+            DecoderEvent::Error(DecodeError::Unknown(MorseCode::from_str("..--"))),
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn reports_overlong_sequences_once() {
+    let events = decode(Timing::new(60), &keying("............ .-", 60, 60));
+    assert_eq!(
+        characters(&events),
+        [
+            DecoderEvent::Error(DecodeError::TooLong(MorseCode::from_str(".........."))),
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn only_types_one_space_per_word_gap() {
+    let mut signal = keying(". / .", 60, 60);
+    signal.push((false, 5000));
+    assert_eq!(text(&decode(Timing::new(60), &signal)), "e e ");
+}
+
+#[test]
+fn adapts_to_the_operators_speed() {
+    // start at 20 WPM, but the operator is sending at 12 WPM then 30 WPM
+    let paris = ".--. .- .-. .. ... / .--. .- .-. .. ... / .--. .- .-. .. ...";
+    let mut signal = keying(paris, 100, 100);
+    signal.extend(keying(paris, 40, 40));
+
+    // the first word after a sudden change in speed may be garbled, but the
+    // decoder should have caught up by the end of it
+    let text = text(&decode(Timing::adaptive(60), &signal));
+    assert!(text.starts_with("paris paris paris "), "{text}");
+    assert!(text.ends_with(" paris paris "), "{text}");
+}
+
+#[test]
+fn farnsworth_stretches_the_gaps() {
+    // characters at 20 WPM, with gaps stretched to give 5 WPM overall
+    let timing = Timing::farnsworth(20.0, 5.0);
+    let spacing_ms = 533;
+
+    let signal = keying("-.- . -.-- / .-", 60, spacing_ms);
+    assert_eq!(text(&decode(timing, &signal)), "key a ");
+
+    // a standard letter gap is too short to end a character at this speed
+    let mut signal = keying(". .", 60, 60);
+    signal.push((false, 10 * spacing_ms));
+    assert_eq!(text(&decode(timing, &signal)), "i ");
+}
+
+#[test]
+fn reports_the_speed() {
+    let decoder = Decoder::new(Timing::new(60), 0u64);
+    assert_eq!(decoder.wpm(), 20.0);
+}
+
+#[test]
+fn ignores_short_taps() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let signal = [(true, 60), (false, 100), (true, 5), (false, 1000)];
+
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "e ");
+    assert_eq!(decoder.glitches_rejected(), 1);
+}
+
+#[test]
+fn ignored_taps_dont_split_gaps() {
+    // the gaps either side of the tap add up to a letter gap
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let signal = [
+        (true, 60),
+        (false, 100),
+        (true, 5),
+        (false, 50),
+        (true, 60),
+        (false, 1000),
+    ];
+
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "ee ");
+    assert_eq!(decoder.glitches_rejected(), 1);
+}
+
+#[test]
+fn merges_key_presses_split_by_short_gaps() {
+    // a dah with a brief release in the middle
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let signal = [(true, 100), (false, 5), (true, 80), (false, 1000)];
+
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "t ");
+    assert_eq!(decoder.glitches_rejected(), 1);
+}
+
+#[test]
+fn ignores_contact_bounce() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let signal = [
+        (true, 2),
+        (false, 1),
+        (true, 2),
+        (false, 2),
+        (true, 60),
+        (false, 1),
+        (true, 1),
+        (false, 1000),
+    ];
+
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "e ");
+    assert_eq!(decoder.glitches_rejected(), 3);
+}
+
+#[test]
+fn glitch_rejection_can_be_disabled() {
+    let timing = Timing {
+        min_element: 0.0,
+        min_gap: 0.0,
+        ..Timing::new(60)
+    };
+
+    let signal = [(true, 5), (false, 1000)];
+    assert_eq!(text(&decode(timing, &signal)), "e ");
+
+    let signal = [(true, 60), (false, 5), (true, 60), (false, 1000)];
+    assert_eq!(text(&decode(timing, &signal)), "i ");
+}
+
+#[test]
+fn reports_each_element_with_its_length() {
+    let signal = [(true, 70), (false, 60), (true, 170), (false, 1000)];
+    assert_eq!(
+        decode(Timing::new(60), &signal),
+        [
+            DecoderEvent::Element {
+                element: Element::Dit,
+                duration_ms: 70,
+            },
+            DecoderEvent::Element {
+                element: Element::Dah,
+                duration_ms: 170,
+            },
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn reports_elements_of_invalid_sequences() {
+    let events = decode(Timing::new(60), &keying("..--", 60, 60));
+    let elements = events
+        .iter()
+        .filter(|event| matches!(event, DecoderEvent::Element { .. }))
+        .count();
+    assert_eq!(elements, 4);
+}
+
+#[test]
+fn reports_everything_found_in_one_push() {
+    // only pushing the edges, so the last element, letter and word all end on
+    // the final push
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    assert_eq!(decoder.push(true, 100).count(), 0);
+    assert_eq!(decoder.push(false, 160).count(), 0);
+    assert_eq!(
+        decoder.push(false, 2000).collect::<Vec<_>>(),
+        [
+            DecoderEvent::Element {
+                element: Element::Dit,
+                duration_ms: 60,
+            },
+            DecoderEvent::Character('e'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+/// Decodes "..--" followed by "a", with the given error policy
+fn decode_error(error_policy: ErrorPolicy, signal: &[(bool, u64)]) -> Vec<DecoderEvent> {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.error_policy = error_policy;
+    characters(&decode_with(&mut decoder, signal))
+}
+
+#[test]
+fn ignores_errors_by_default() {
+    let signal = keying("..-- .-", 60, 60);
+    assert_eq!(
+        decode_error(ErrorPolicy::default(), &signal),
+        [
+            DecoderEvent::Error(DecodeError::Unknown(MorseCode::from_str("..--"))),
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn types_a_placeholder_for_errors() {
+    let signal = keying("..-- .-", 60, 60);
+    assert_eq!(
+        decode_error(ErrorPolicy::Placeholder('?'), &signal),
+        [
+            DecoderEvent::Error(DecodeError::Unknown(MorseCode::from_str("..--"))),
+            DecoderEvent::Character('?'),
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn types_the_raw_pattern_for_errors() {
+    let signal = keying("..-- .-", 60, 60);
+    let code = MorseCode::from_str("..--");
+    assert_eq!(
+        decode_error(ErrorPolicy::RawPattern, &signal),
+        [
+            DecoderEvent::Error(DecodeError::Unknown(code)),
+            DecoderEvent::Pattern(code),
+            DecoderEvent::Character('a'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn types_the_best_guess_for_errors() {
+    // the last dah is only just long enough to be a dah, so was probably "f"
+    let signal = [
+        (true, 60),
+        (false, 60),
+        (true, 60),
+        (false, 60),
+        (true, 180),
+        (false, 60),
+        (true, 125),
+        (false, 1000),
+    ];
+    assert_eq!(
+        decode_error(ErrorPolicy::BestGuess, &signal),
+        [
+            DecoderEvent::Error(DecodeError::Unknown(MorseCode::from_str("..--"))),
+            DecoderEvent::Character('f'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn doesnt_guess_sequences_that_are_too_long() {
+    let signal = keying("............", 60, 60);
+    assert_eq!(
+        decode_error(ErrorPolicy::BestGuess, &signal),
+        [
+            DecoderEvent::Error(DecodeError::TooLong(MorseCode::from_str(".........."))),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn reports_every_event_when_an_error_ends_a_word_in_one_push() {
+    // only pushing the edges of "..--", so the last element, the error and the
+    // word gap all end on the final push
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.error_policy = ErrorPolicy::Placeholder('_');
+    for (at, high) in [0, 60, 120, 180, 240, 420, 480, 660]
+        .into_iter()
+        .zip([true, false].into_iter().cycle())
+    {
+        decoder.push(high, at);
+    }
+
+    assert_eq!(
+        decoder.push(false, 3000).collect::<Vec<_>>(),
+        [
+            DecoderEvent::Element {
+                element: Element::Dah,
+                duration_ms: 180,
+            },
+            DecoderEvent::Error(DecodeError::Unknown(MorseCode::from_str("..--"))),
+            DecoderEvent::Character('_'),
+            DecoderEvent::WordGap,
+        ]
+    );
+}
+
+#[test]
+fn reports_short_error_signals() {
+    let signal = keying("...... / ....... / ........ / .....", 60, 60);
+    assert_eq!(
+        text(&decode(Timing::new(60), &signal)),
+        "<short error><short error><HH>5 "
+    );
+}
+
+#[test]
+fn corrections_on_their_own_are_not_followed_by_a_word_gap() {
+    for (code, correction) in [
+        ("......", DecoderEvent::ShortError),
+        ("........", DecoderEvent::Prosign(Prosign::HH)),
+    ] {
+        let events = decode(Timing::new(60), &keying(code, 60, 60));
+        assert_eq!(characters(&events), [correction], "{code}");
+    }
+}
+
+#[test]
+fn decodes_national_letters_with_international_figures() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::Cyrillic);
+    let signal = keying(".--. .-. .. .-- . - / .---- ..--- ..--..", 60, 60);
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "привет 12? ");
+}
+
+#[test]
+fn decodes_multiple_characters_from_one_code() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::Latin);
+    let events = decode_with(&mut decoder, &keying("---- .-.-", 60, 60));
+    assert_eq!(
+        characters(&events),
+        [
+            DecoderEvent::Text("ch"),
+            DecoderEvent::Character('ä'),
+            DecoderEvent::WordGap
+        ]
+    );
+}
+
+#[test]
+fn switches_tables_at_runtime() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::Greek);
+    let events = decode_with(&mut decoder, &keying("-- --- .-. ... .", 60, 60));
+    assert_eq!(text(&events), "μορσε ");
+
+    decoder.set_table(CodeTable::International);
+    let events = decode_with(&mut decoder, &keying("-- --- .-. ... .", 60, 60));
+    assert_eq!(text(&events), "morse ");
+}
+
+#[test]
+fn switches_tables_without_a_word_gap() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let events = decode_with(&mut decoder, &keying("-..---", 60, 60));
+    assert_eq!(characters(&events), [DecoderEvent::Prosign(Prosign::DO)]);
+    assert_eq!(decoder.table(), CodeTable::Wabun);
+}
+
+#[test]
+fn switches_to_wabun_on_do_and_back_on_sn() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    let signal = keying("-..--- / .- .-.-. / ...-. / .- .-.-.", 60, 60);
+    assert_eq!(
+        text(&decode_with(&mut decoder, &signal)),
+        "<DO>イン <SN>a<AR> "
+    );
+    assert_eq!(decoder.table(), CodeTable::International);
+}
+
+#[test]
+fn returns_to_the_previous_table_after_wabun() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::Cyrillic);
+    decode_with(&mut decoder, &keying("-..---", 60, 60));
+    assert_eq!(decoder.table(), CodeTable::Wabun);
+    decode_with(&mut decoder, &keying("...-.", 60, 60));
+    assert_eq!(decoder.table(), CodeTable::Cyrillic);
+}
+
+/// Converts American Morse characters into perfectly timed key presses, with
+/// the standard 2 dit internal spaces and 4 dit letter gaps
+fn american_keying(letters: &[&str], dit_ms: u64) -> Vec<(bool, u64)> {
+    let mut signal = Vec::new();
+    for (i, letter) in letters.iter().enumerate() {
+        if i > 0 {
+            signal.push((false, 4 * dit_ms));
+        }
+        let mut gap = 0;
+        for element in letter.chars() {
+            let units = match element {
+                ' ' => {
+                    gap = 2;
+                    continue;
+                }
+                '.' => 1,
+                '-' => 3,
+                '_' => 6,
+                '=' => 9,
+                _ => panic!("unknown element {element}"),
+            };
+            if gap > 0 {
+                signal.push((false, gap * dit_ms));
+            }
+            signal.push((true, units * dit_ms));
+            gap = 1;
+        }
+    }
+    signal.push((false, 10 * dit_ms));
+    signal
+}
+
+#[test]
+fn decodes_american_spaces_and_long_dashes() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::American);
+    let letters = [".. .", ". .", ". ..", ".. ..", "... .", ". ...", "_", "="];
+    let events = decode_with(&mut decoder, &american_keying(&letters, 60));
+    assert_eq!(text(&events), "coryz&l0 ");
+}
+
+#[test]
+fn reports_american_elements() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_table(CodeTable::American);
+    let events = decode_with(&mut decoder, &american_keying(&[". ..", "_"], 60));
+    let elements: Vec<Element> = events
+        .iter()
+        .filter_map(|event| match event {
+            DecoderEvent::Element { element, .. } => Some(*element),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        elements,
+        [
+            Element::Dit,
+            Element::Space,
+            Element::Dit,
+            Element::Dit,
+            Element::LongDah
+        ]
+    );
+}
+
+#[test]
+fn decodes_american_morse_with_adaptive_timing() {
+    let mut decoder = Decoder::new(Timing::adaptive(60), 0);
+    decoder.set_table(CodeTable::American);
+    let letters = [".. .", ". .", "_", "_", ".-", ". .."];
+    let events = decode_with(&mut decoder, &american_keying(&letters, 80));
+    assert_eq!(text(&events), "collar ");
+}
+
+#[test]
+fn ignores_internal_spaces_and_long_dashes_in_international_mode() {
+    let events = decode(Timing::new(60), &american_keying(&[". .", "_"], 60));
+    assert_eq!(text(&events), "eet ");
+}
+
+#[test]
+fn types_cut_numbers_as_digits_until_the_end_of_the_word() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    assert!(decoder.toggle_cut_numbers());
+    // 5NN TU, then 5NN again
+    let signal = keying("..... -. -. / - ..- / ..... -. -.", 60, 60);
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "599 tu 5nn ");
+    assert!(!decoder.cut_numbers());
+}
+
+#[test]
+fn converts_every_cut_number() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.toggle_cut_numbers();
+    let signal = keying("- .- ..- ...- . -.. -. -.-", 60, 60);
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "0123589k ");
+}
+
+#[test]
+fn toggles_cut_numbers_with_a_prosign() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.cut_numbers_prosign = Some(Prosign::CT);
+    // the pause after the prosign doesn't end the number, as it hasn't
+    // started yet
+    let signal = keying("-.-.- / . -. -. / . -. -.", 60, 60);
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "599 enn ");
+}
+
+#[test]
+fn types_nothing_for_the_cut_numbers_prosign() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.cut_numbers_prosign = Some(Prosign::KN);
+    let events = decode_with(&mut decoder, &keying("-.--. / -.--.", 60, 60));
+    assert_eq!(characters(&events), []);
+    assert!(!decoder.cut_numbers());
+}
+
+#[test]
+fn cut_numbers_can_be_switched_off_again() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.cut_numbers_prosign = Some(Prosign::CT);
+    let signal = keying("-.-.- -. -.-.- -.", 60, 60);
+    assert_eq!(text(&decode_with(&mut decoder, &signal)), "9n ");
+
+    assert!(decoder.toggle_cut_numbers());
+    assert!(!decoder.toggle_cut_numbers());
+}
+
+#[test]
+fn forgets_the_character_being_sent_when_reset() {
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    // a dit and the start of another element, then nothing could be read for
+    // a second
+    let mut events = decode_with(&mut decoder, &[(true, 60), (false, 60), (true, 100)]);
+    decoder.reset(1220);
+
+    // a dah once the key can be read again
+    for now in 1221..3000 {
+        events.extend(decoder.push(now <= 1400, now));
+    }
+    assert_eq!(text(&events), "t ");
+}
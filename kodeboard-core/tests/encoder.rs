@@ -0,0 +1,254 @@
+use kodeboard_core::code_table::{CodeTable, Decoded, Prosign};
+use kodeboard_core::decoder::{Decoder, DecoderEvent, Timing};
+use kodeboard_core::encoder::Encoder;
+use proptest::prelude::*;
+
+/// Every character the decoder can type, apart from spaces
+const CHARACTERS: &str = "abcdefghijklmnopqrstuvwxyzé0123456789.,?'!/)&:;-_\"$@";
+
+fn encode(text: &str, timing: Timing) -> Vec<(bool, u64)> {
+    Encoder::new(text, timing).collect()
+}
+
+/// The key presses for a single character, written in dits and dahs
+fn keying(code: &str, dit_ms: u64) -> Vec<(bool, u64)> {
+    let mut signal = Vec::new();
+    for element in code.chars() {
+        if !signal.is_empty() {
+            signal.push((false, dit_ms));
+        }
+        signal.push((true, if element == '.' { dit_ms } else { 3 * dit_ms }));
+    }
+    signal
+}
+
+/// Feeds the key presses to a decoder once per millisecond, like the firmware
+/// does, and writes out what it decoded
+fn decode(timing: Timing, signal: impl IntoIterator<Item = (bool, u64)>) -> String {
+    decode_table(timing, CodeTable::International, signal)
+}
+
+/// Decodes the key presses using the given code table
+fn decode_table(
+    timing: Timing,
+    table: CodeTable,
+    signal: impl IntoIterator<Item = (bool, u64)>,
+) -> String {
+    let mut decoder = Decoder::new(timing, 0u64);
+    decoder.set_table(table);
+    let mut now = 0;
+    let mut text = String::new();
+
+    for (key_down, duration_ms) in signal {
+        for _ in 0..duration_ms {
+            now += 1;
+            for event in decoder.push(key_down, now) {
+                match event {
+                    DecoderEvent::Element { .. } => {}
+                    DecoderEvent::Character(c) => text.push(c),
+                    DecoderEvent::Text(decoded) => text.push_str(decoded),
+                    DecoderEvent::Prosign(prosign) => {
+                        text.push_str(&format!("<{}>", prosign.name()))
+                    }
+                    DecoderEvent::WordGap => text.push(' '),
+                    DecoderEvent::ShortError => text.push('#'),
+                    DecoderEvent::Error(_)
+                    | DecoderEvent::Pattern(_)
+                    | DecoderEvent::UserCode(_) => text.push('#'),
+                }
+            }
+        }
+    }
+
+    text
+}
+
+#[test]
+fn encodes_elements_and_element_gaps() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("e", timing), [(true, 60)]);
+    assert_eq!(encode("a", timing), [(true, 60), (false, 60), (true, 180)]);
+}
+
+#[test]
+fn separates_letters_and_words() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("ee", timing), [(true, 60), (false, 180), (true, 60)]);
+    assert_eq!(
+        encode("e e", timing),
+        [(true, 60), (false, 420), (true, 60)]
+    );
+
+    // repeated and leading whitespace makes no difference
+    assert_eq!(encode("  e \t\n e", timing), encode("e e", timing));
+}
+
+#[test]
+fn ends_with_a_word_gap_after_trailing_whitespace() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("e ", timing), [(true, 60), (false, 420)]);
+    assert_eq!(encode("", timing), []);
+    assert_eq!(encode(" ", timing), []);
+}
+
+#[test]
+fn ignores_case() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("Morse É", timing), encode("morse é", timing));
+}
+
+#[test]
+fn encodes_prosigns() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("<SK>", timing), keying("...-.-", 60));
+    assert_eq!(encode("<kn>", timing), keying("-.--.", 60));
+
+    // characters that share a code with a prosign
+    assert_eq!(encode("+", timing), keying(".-.-.", 60));
+    assert_eq!(encode("=", timing), keying("-...-", 60));
+    assert_eq!(encode("(", timing), keying("-.--.", 60));
+}
+
+#[test]
+fn treats_unknown_prosigns_as_text() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("<xy>", timing), encode("xy", timing));
+}
+
+#[test]
+fn skips_characters_without_a_code() {
+    let timing = Timing::new(60);
+    assert_eq!(encode("e#e", timing), encode("ee", timing));
+    assert_eq!(encode("e # e", timing), encode("e e", timing));
+}
+
+#[test]
+fn stretches_gaps_for_farnsworth_timing() {
+    // characters at 20 WPM, with gaps stretched to give 10 WPM overall
+    let timing = Timing::farnsworth(20.0, 10.0);
+    let spacing_ms = timing.spacing_unit_ms(60.0);
+    assert!(spacing_ms > 60.0);
+
+    let letter_gap_ms = (3.0 * spacing_ms) as u64;
+    let word_gap_ms = (7.0 * spacing_ms) as u64;
+    assert_eq!(
+        encode("ai e", timing),
+        [
+            (true, 60),
+            (false, 60),
+            (true, 180),
+            (false, letter_gap_ms),
+            (true, 60),
+            (false, 60),
+            (true, 60),
+            (false, word_gap_ms),
+            (true, 60),
+        ]
+    );
+}
+
+#[test]
+fn sends_paris_in_fifty_units() {
+    // "PARIS " is the standard word used to measure speed, so at 20 WPM it
+    // takes exactly three seconds
+    let total_ms: u64 = Encoder::new("paris ", Timing::new(60))
+        .map(|(_, duration_ms)| duration_ms)
+        .sum();
+    assert_eq!(total_ms, 50 * 60);
+
+    // and at 5 WPM overall with Farnsworth timing it takes 12 seconds
+    let total_ms: u64 = Encoder::new("paris ", Timing::farnsworth(20.0, 5.0))
+        .map(|(_, duration_ms)| duration_ms)
+        .sum();
+    assert!(total_ms.abs_diff(12_000) < 20, "{total_ms}");
+}
+
+#[test]
+fn alternates_key_down_and_up() {
+    let signal = encode("cq cq de <KN>", Timing::new(60));
+    assert!(signal.windows(2).all(|pair| pair[0].0 != pair[1].0));
+    assert!(signal[0].0);
+}
+
+#[test]
+fn decodes_every_character_it_encodes_in_every_table() {
+    let timing = Timing::new(60);
+    for table in CodeTable::ALL {
+        table.tree().for_each_entry(|_, decoded| {
+            if let Decoded::Char(c) = decoded {
+                let text = format!("{c} ");
+                let signal = Encoder::new(&text, timing).with_table(table);
+                assert_eq!(decode_table(timing, table, signal), text, "{table:?}");
+            }
+        });
+    }
+}
+
+#[test]
+fn sends_american_spaces_and_long_dashes() {
+    let timing = Timing::new(60);
+    let signal: Vec<_> = Encoder::new("cl", timing)
+        .with_table(CodeTable::American)
+        .collect();
+    assert_eq!(
+        signal,
+        [
+            (true, 60),
+            (false, 60),
+            (true, 60),
+            (false, 120),
+            (true, 60),
+            (false, 240),
+            (true, 360)
+        ]
+    );
+}
+
+#[test]
+fn decodes_american_morse_it_encodes_with_farnsworth_timing() {
+    let timing = Timing::farnsworth(20.0, 10.0);
+    let text = "the lazy dog crossed 10 roads ";
+    let signal = Encoder::new(text, timing).with_table(CodeTable::American);
+    assert_eq!(decode_table(timing, CodeTable::American, signal), text);
+}
+
+/// A word made of characters from the code table
+fn word() -> impl Strategy<Value = String> {
+    let characters: Vec<char> = CHARACTERS.chars().collect();
+    prop::collection::vec(prop::sample::select(characters), 1..8)
+        .prop_map(|characters| characters.into_iter().collect())
+}
+
+/// Some words, each followed by a space
+fn sentence() -> impl Strategy<Value = String> {
+    prop::collection::vec(word(), 1..6)
+        .prop_map(|words| words.into_iter().map(|word| word + " ").collect::<String>())
+}
+
+proptest! {
+    #[test]
+    fn decodes_what_it_encodes(text in sentence(), dit_ms in 20u64..200) {
+        let timing = Timing::new(dit_ms);
+        prop_assert_eq!(decode(timing, Encoder::new(&text, timing)), text);
+    }
+
+    #[test]
+    fn decodes_what_it_encodes_with_farnsworth_timing(
+        text in sentence(),
+        character_wpm in 15.0f32..40.0,
+        slowdown in 1.0f32..4.0,
+    ) {
+        let timing = Timing::farnsworth(character_wpm, character_wpm / slowdown);
+        prop_assert_eq!(decode(timing, Encoder::new(&text, timing)), text);
+    }
+
+    #[test]
+    fn decodes_prosigns_it_encodes(index in 0..Prosign::COUNT, dit_ms in 20u64..200) {
+        let prosign = Prosign::ALL[index];
+        let text = format!("<{}>", prosign.name());
+        let timing = Timing::new(dit_ms);
+        // a prosign doesn't start a word, so the space after it decodes to nothing
+        let sent = text.clone() + " ";
+        prop_assert_eq!(decode(timing, Encoder::new(&sent, timing)), text);
+    }
+}
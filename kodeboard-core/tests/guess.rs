@@ -0,0 +1,57 @@
+use kodeboard_core::code_table::{self, Decoded, Element, MorseCode};
+use kodeboard_core::guess::best_guess;
+
+/// The threshold for a 60ms dit
+const DAH_THRESHOLD_MS: f32 = 120.0;
+
+fn guess(durations_ms: &[u64]) -> Option<Decoded> {
+    let mut sent = MorseCode::new();
+    for &duration_ms in durations_ms {
+        sent.push(if (duration_ms as f32) < DAH_THRESHOLD_MS {
+            Element::Dit
+        } else {
+            Element::Dah
+        });
+    }
+    best_guess(&code_table::ITU, &sent, durations_ms, DAH_THRESHOLD_MS)
+}
+
+#[test]
+fn returns_exact_matches() {
+    assert_eq!(guess(&[60, 180]), Some(Decoded::Char('a')));
+    assert_eq!(guess(&[60, 60, 180, 180, 180]), Some(Decoded::Char('2')));
+}
+
+#[test]
+fn swaps_elements_that_were_close_to_the_threshold() {
+    // "..--" isn't a code, but the last dah was nearly a dit so it was "f"
+    assert_eq!(guess(&[60, 60, 180, 130]), Some(Decoded::Char('f')));
+
+    // ".-.-" isn't a code, but the second dit was nearly a dah so it was "j"
+    assert_eq!(guess(&[60, 180, 110, 180]), Some(Decoded::Char('j')));
+}
+
+#[test]
+fn prefers_swapping_an_element_to_adding_or_removing_one() {
+    // ".-..-" could be "l" with an extra dah, but the dah was short so it was "&"
+    assert_eq!(guess(&[60, 180, 60, 60, 125]), Some(Decoded::Char('&')));
+}
+
+#[test]
+fn adds_or_removes_elements_when_the_timing_was_clear() {
+    // "......" has one too many dits for "5"
+    assert_eq!(guess(&[60; 6]), Some(Decoded::Char('5')));
+}
+
+#[test]
+fn never_guesses_prosigns() {
+    // too many dits for HH is still only a long "5"
+    assert_eq!(guess(&[60; 9]), Some(Decoded::Char('5')));
+    assert_eq!(guess(&[60; 10]), Some(Decoded::Char('5')));
+
+    // even an exact match for AR guesses a character instead
+    assert!(!matches!(
+        guess(&[60, 180, 60, 180, 60]),
+        Some(Decoded::Prosign(_))
+    ));
+}
@@ -0,0 +1,288 @@
+use std::iter;
+
+use kodeboard_core::hid::{
+    HidState, KeyReport, MAX_KEYS, NKRO_REPORT_LEN, Protocol, REPORT_LEN, ReportQueue,
+};
+use kodeboard_core::host::Host;
+use kodeboard_core::layout::{ALT, KeyStroke, Layout, SHIFT};
+use kodeboard_core::unicode::UnicodeEntry;
+
+fn key(keycode: u8) -> KeyStroke {
+    KeyStroke::new(0, keycode)
+}
+
+fn shift(keycode: u8) -> KeyStroke {
+    KeyStroke::new(SHIFT, keycode)
+}
+
+#[test]
+fn lays_reports_out_like_the_boot_keyboard_report() {
+    let report = KeyReport::new(SHIFT, 0x04);
+    let bytes: [u8; REPORT_LEN] = report.to_bytes();
+    assert_eq!(bytes, [SHIFT, 0, 0x04, 0, 0, 0, 0, 0]);
+
+    let report = KeyReport {
+        modifier: 0,
+        keycodes: [1, 2, 3, 4, 5, 6],
+    };
+    assert_eq!(report.to_bytes(), [0, 0, 1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn starts_in_report_protocol() {
+    let hid = HidState::<u64>::new(false);
+    assert_eq!(hid.protocol(), Protocol::Report);
+    assert_eq!(hid.idle_ms(), HidState::<u64>::DEFAULT_IDLE_MS);
+    assert_eq!(hid.report(), KeyReport::default());
+}
+
+#[test]
+fn switches_protocol() {
+    let mut hid = HidState::<u64>::new(false);
+    hid.set_protocol(Protocol::Boot);
+    assert_eq!(hid.protocol(), Protocol::Boot);
+    hid.set_protocol(Protocol::Report);
+    assert_eq!(hid.protocol(), Protocol::Report);
+}
+
+#[test]
+fn reports_the_keys_that_are_down() {
+    let mut hid = HidState::new(false);
+    hid.sent(KeyReport::new(0, 0x06), 10);
+    assert_eq!(hid.report(), KeyReport::new(0, 0x06));
+    hid.sent(KeyReport::new(0, 0), 20);
+    assert_eq!(hid.report(), KeyReport::new(0, 0));
+}
+
+#[test]
+fn repeats_the_last_report_at_the_idle_rate() {
+    let mut hid = HidState::new(false);
+    // 25 units of 4 ms
+    hid.set_idle(25);
+    assert_eq!(hid.idle_ms(), 100);
+    hid.sent(KeyReport::new(0, 0x06), 1000);
+
+    assert_eq!(hid.next_repeat(), Some(1100));
+    assert_eq!(hid.repeat(1050), None);
+    assert_eq!(hid.repeat(1100), Some(KeyReport::new(0, 0x06)));
+    assert_eq!(hid.next_repeat(), Some(1200));
+    assert_eq!(hid.repeat(1150), None);
+    assert_eq!(hid.repeat(1200), Some(KeyReport::new(0, 0x06)));
+
+    // a new report starts the idle period again
+    hid.sent(KeyReport::new(0, 0), 1250);
+    assert_eq!(hid.repeat(1300), None);
+    assert_eq!(hid.repeat(1350), Some(KeyReport::new(0, 0)));
+}
+
+#[test]
+fn only_sends_changes_with_an_idle_rate_of_zero() {
+    let mut hid = HidState::new(false);
+    // SET_IDLE with a duration of zero
+    hid.set_idle(0);
+    assert_eq!(hid.idle_ms(), 0);
+    hid.sent(KeyReport::new(0, 0x06), 0);
+    assert_eq!(hid.next_repeat(), None);
+    assert_eq!(hid.repeat(10_000), None);
+}
+
+#[test]
+fn answers_get_idle_in_the_units_of_set_idle() {
+    let mut hid = HidState::<u64>::new(false);
+    assert_eq!(hid.idle(), 125);
+    hid.set_idle(255);
+    assert_eq!(hid.idle(), 255);
+    assert_eq!(hid.idle_ms(), 1020);
+}
+
+#[test]
+fn waits_for_the_first_report_before_repeating() {
+    let mut hid = HidState::<u64>::new(false);
+    assert_eq!(hid.next_repeat(), None);
+    assert_eq!(hid.repeat(10_000), None);
+}
+
+#[test]
+fn resets_to_the_defaults() {
+    let mut hid = HidState::new(false);
+    hid.set_protocol(Protocol::Boot);
+    hid.set_idle(0);
+    hid.sent(KeyReport::new(0, 0x06), 0);
+
+    hid.reset();
+    assert_eq!(hid.protocol(), Protocol::Report);
+    assert_eq!(hid.idle_ms(), HidState::<u64>::DEFAULT_IDLE_MS);
+    assert_eq!(hid.repeat(10_000), None);
+    assert_eq!(hid.report(), KeyReport::new(0, 0x06));
+}
+
+#[test]
+fn encodes_nkro_reports_as_a_bitmap() {
+    let report = KeyReport {
+        modifier: SHIFT,
+        keycodes: [0x04, 0x05, 0x28, 0, 0, 0],
+    };
+    let bytes: [u8; NKRO_REPORT_LEN] = report.to_nkro_bytes();
+    assert_eq!(bytes[0], SHIFT);
+    assert_eq!(bytes[1], 0b0011_0000);
+    assert_eq!(bytes[6], 0b0000_0001);
+    assert_eq!(bytes.iter().map(|byte| byte.count_ones()).sum::<u32>(), 4);
+}
+
+#[test]
+fn only_uses_nkro_reports_under_the_report_protocol() {
+    let report = KeyReport::new(0, 0x04);
+    let mut hid = HidState::<u64>::new(true);
+    assert_eq!(hid.encode(report).as_bytes(), report.to_nkro_bytes());
+
+    hid.set_protocol(Protocol::Boot);
+    assert_eq!(hid.encode(report).as_bytes(), report.to_bytes());
+
+    let hid = HidState::<u64>::new(false);
+    assert_eq!(hid.encode(report).as_bytes(), report.to_bytes());
+}
+
+/// Queues the strokes for each character in turn and then releases them,
+/// returning every report that would be sent
+fn reports(strokes: &[KeyStroke]) -> Vec<KeyReport> {
+    let mut queue = ReportQueue::default();
+    let mut reports = Vec::new();
+    for &stroke in strokes {
+        queue.press(stroke).unwrap();
+        reports.extend(iter::from_fn(|| queue.pop()));
+    }
+    queue.release_all().unwrap();
+    reports.extend(iter::from_fn(|| queue.pop()));
+    reports
+}
+
+fn keys(modifier: u8, keycodes: &[u8]) -> KeyReport {
+    let mut report = KeyReport::new(modifier, 0);
+    report.keycodes[..keycodes.len()].copy_from_slice(keycodes);
+    report
+}
+
+#[test]
+fn adds_one_key_to_each_report() {
+    let reports = reports(&[key(0x04), key(0x05), key(0x06)]);
+    assert_eq!(
+        reports,
+        [
+            keys(0, &[0x04]),
+            keys(0, &[0x04, 0x05]),
+            keys(0, &[0x04, 0x05, 0x06]),
+            keys(0, &[]),
+        ]
+    );
+}
+
+#[test]
+fn releases_keys_before_pressing_one_again() {
+    // "eel"
+    let reports = reports(&[key(0x08), key(0x08), key(0x0F)]);
+    assert_eq!(
+        reports,
+        [
+            keys(0, &[0x08]),
+            keys(0, &[]),
+            keys(0, &[0x08]),
+            keys(0, &[0x08, 0x0F]),
+            keys(0, &[]),
+        ]
+    );
+}
+
+#[test]
+fn releases_keys_before_changing_modifiers() {
+    let reports = reports(&[key(0x04), shift(0x05), shift(0x06), key(0x07)]);
+    assert_eq!(
+        reports,
+        [
+            keys(0, &[0x04]),
+            keys(0, &[]),
+            keys(SHIFT, &[0x05]),
+            keys(SHIFT, &[0x05, 0x06]),
+            keys(SHIFT, &[]),
+            keys(0, &[0x07]),
+            keys(0, &[]),
+        ]
+    );
+}
+
+#[test]
+fn releases_keys_when_the_report_is_full() {
+    let strokes: Vec<_> = (0x04..0x0B).map(key).collect();
+    let reports = reports(&strokes);
+    assert_eq!(reports[5], keys(0, &[0x04, 0x05, 0x06, 0x07, 0x08, 0x09]));
+    assert_eq!(reports[6], keys(0, &[]));
+    assert_eq!(reports[7], keys(0, &[0x0A]));
+    assert_eq!(reports.len(), 9);
+}
+
+#[test]
+fn never_holds_more_than_six_keys() {
+    let strokes: Vec<_> = (0..100).map(|i| key(0x04 + i % 26)).collect();
+    for report in reports(&strokes) {
+        assert!(report.key_count() <= MAX_KEYS);
+    }
+}
+
+#[test]
+fn types_every_key_in_order() {
+    // each key press should be the one new key in its report
+    let strokes = [key(0x0B), key(0x08), key(0x0F), key(0x0F), key(0x12)];
+    let mut pressed: Vec<u8> = Vec::new();
+    let mut previous = KeyReport::default();
+    for report in reports(&strokes) {
+        let new: Vec<_> = report
+            .keycodes
+            .into_iter()
+            .filter(|&keycode| keycode != 0 && !previous.contains(keycode))
+            .collect();
+        assert!(new.len() <= 1);
+        pressed.extend(new);
+        previous = report;
+    }
+    assert_eq!(pressed, [0x0B, 0x08, 0x0F, 0x0F, 0x12]);
+}
+
+#[test]
+fn releases_held_modifiers_at_the_end_of_a_sequence() {
+    let host = Host {
+        layout: Layout::Us,
+        unicode_entry: Some(UnicodeEntry::WindowsAltCode),
+    };
+    let mut queue = ReportQueue::default();
+    queue.push_keys(&host.keys_for('é').unwrap()).unwrap();
+    let reports: Vec<_> = iter::from_fn(|| queue.pop()).collect();
+
+    assert_eq!(reports.first(), Some(&keys(ALT, &[0x62])));
+    assert_eq!(reports.last(), Some(&keys(0, &[])));
+    assert_eq!(queue.held(), KeyReport::default());
+}
+
+#[test]
+fn releases_nothing_when_nothing_is_down() {
+    let mut queue = ReportQueue::default();
+    queue.release_all().unwrap();
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn queues_nothing_without_room_for_all_of_a_sequence() {
+    let mut queue = ReportQueue::default();
+    // the same key over and over needs a release between each press
+    while queue.press(key(0x04)).is_ok() {}
+    let len = queue.len();
+    assert!(queue.press(key(0x05)).is_err());
+    assert_eq!(queue.len(), len);
+    assert_eq!(queue.held(), keys(0, &[0x04]));
+
+    // but there is always room for the longest sequence in an empty queue
+    while queue.pop().is_some() {}
+    let host = Host {
+        layout: Layout::Us,
+        unicode_entry: Some(UnicodeEntry::MacOs),
+    };
+    assert!(queue.push_keys(&host.keys_for('😀').unwrap()).is_ok());
+}
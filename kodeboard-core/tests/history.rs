@@ -0,0 +1,85 @@
+use kodeboard_core::history::TypedHistory;
+use kodeboard_core::key_mapping::ControlKey;
+
+fn history(text: &str) -> TypedHistory {
+    let mut history = TypedHistory::default();
+    text.chars().for_each(|c| history.push(c));
+    history
+}
+
+fn text(history: &TypedHistory) -> String {
+    history.typed().iter().collect()
+}
+
+#[test]
+fn deletes_the_last_character() {
+    let mut history = history("morse");
+    assert_eq!(history.delete_character(), 1);
+    assert_eq!(text(&history), "mors");
+}
+
+#[test]
+fn always_deletes_a_character() {
+    let mut history = TypedHistory::default();
+    assert_eq!(history.delete_character(), 1);
+    assert_eq!(text(&history), "");
+}
+
+#[test]
+fn deletes_the_last_word() {
+    let mut history = history("morse code");
+    assert_eq!(history.delete_word(), 4);
+    assert_eq!(text(&history), "morse ");
+
+    assert_eq!(history.delete_word(), 6);
+    assert_eq!(text(&history), "");
+}
+
+#[test]
+fn deletes_spaces_after_the_last_word() {
+    let mut history = history("morse code  ");
+    assert_eq!(history.delete_word(), 6);
+    assert_eq!(text(&history), "morse ");
+}
+
+#[test]
+fn doesnt_delete_text_it_didnt_type() {
+    let mut history = TypedHistory::default();
+    assert_eq!(history.delete_word(), 0);
+}
+
+#[test]
+fn tracks_new_lines_and_tabs() {
+    let mut history = history("cq");
+    history.press(ControlKey::Enter);
+    history.press(ControlKey::Tab);
+    assert_eq!(text(&history), "cq\n\t");
+
+    // they end words like spaces do
+    history.push('d');
+    history.push('e');
+    assert_eq!(history.delete_word(), 2);
+    assert_eq!(history.delete_word(), 4);
+    assert_eq!(text(&history), "");
+}
+
+#[test]
+fn tracks_backspaces() {
+    let mut history = history("de");
+    history.press(ControlKey::Backspace);
+    assert_eq!(text(&history), "d");
+}
+
+#[test]
+fn forgets_everything_after_escape() {
+    let mut history = history("de");
+    history.press(ControlKey::Escape);
+    assert_eq!(history.delete_word(), 0);
+}
+
+#[test]
+fn forgets_the_oldest_characters() {
+    let mut history = history(&"e".repeat(100));
+    assert_eq!(history.typed().len(), 64);
+    assert_eq!(history.delete_word(), 64);
+}
@@ -0,0 +1,103 @@
+use kodeboard_core::host::{Host, HostLeds};
+use kodeboard_core::layout::{ALT_GR, KeyStroke, Layout, SHIFT};
+
+fn key(keycode: u8) -> KeyStroke {
+    KeyStroke::new(0, keycode)
+}
+
+fn shift(keycode: u8) -> KeyStroke {
+    KeyStroke::new(SHIFT, keycode)
+}
+
+/// The key strokes that type a character with the host's Caps Lock on
+fn caps_lock_keys(layout: Layout, c: char) -> Vec<KeyStroke> {
+    let host = Host {
+        layout,
+        unicode_entry: None,
+    };
+    let keys = host
+        .keys_for_caps_lock(c, true)
+        .expect("can't type character");
+    keys.strokes().to_vec()
+}
+
+#[test]
+fn reads_leds_from_output_reports() {
+    let leds = HostLeds::from_report(&[0x02]).unwrap();
+    assert!(leds.caps_lock());
+    assert!(!leds.num_lock());
+    assert!(!leds.scroll_lock());
+
+    let leds = HostLeds::from_report(&[0x05]).unwrap();
+    assert!(!leds.caps_lock());
+    assert!(leds.num_lock());
+    assert!(leds.scroll_lock());
+
+    assert_eq!(HostLeds::from_report(&[]), None);
+}
+
+#[test]
+fn toggles_caps_lock() {
+    let mut leds = HostLeds::default();
+    leds.toggle_caps_lock();
+    assert!(leds.caps_lock());
+    leds.toggle_caps_lock();
+    assert!(!leds.caps_lock());
+}
+
+#[test]
+fn swaps_shift_on_letters_with_caps_lock() {
+    assert_eq!(caps_lock_keys(Layout::Us, 'a'), [shift(0x04)]);
+    assert_eq!(caps_lock_keys(Layout::Us, 'A'), [key(0x04)]);
+    assert_eq!(caps_lock_keys(Layout::De, 'ä'), [shift(0x34)]);
+}
+
+#[test]
+fn leaves_other_keys_alone_with_caps_lock() {
+    assert_eq!(caps_lock_keys(Layout::Us, '1'), [key(0x1E)]);
+    assert_eq!(caps_lock_keys(Layout::Us, '!'), [shift(0x1E)]);
+    assert_eq!(caps_lock_keys(Layout::De, 'ß'), [key(0x2D)]);
+    assert_eq!(
+        caps_lock_keys(Layout::De, '@'),
+        [KeyStroke::new(ALT_GR, 0x14)]
+    );
+}
+
+#[test]
+fn swaps_the_letter_after_a_dead_key_with_caps_lock() {
+    assert_eq!(caps_lock_keys(Layout::De, 'é'), [key(0x2E), shift(0x08)]);
+}
+
+#[test]
+fn types_normally_without_caps_lock() {
+    let host = Host::default();
+    for c in ['a', 'A', '1'] {
+        assert_eq!(host.keys_for_caps_lock(c, false), host.keys_for(c));
+    }
+}
+
+#[test]
+fn shifts_characters_like_the_host_would() {
+    let us = Host::default();
+    assert_eq!(us.shifted('a'), 'A');
+    assert_eq!(us.shifted('1'), '!');
+    assert_eq!(us.shifted(';'), ':');
+    // already shifted, or not changed by shift
+    assert_eq!(us.shifted('A'), 'A');
+    assert_eq!(us.shifted('!'), '!');
+    assert_eq!(us.shifted(' '), ' ');
+
+    let de = Host {
+        layout: Layout::De,
+        unicode_entry: None,
+    };
+    assert_eq!(de.shifted('1'), '!');
+    assert_eq!(de.shifted('ä'), 'Ä');
+    assert_eq!(de.shifted('ß'), '?');
+    assert_eq!(de.shifted('@'), '@');
+}
+
+#[test]
+fn puts_letters_without_a_key_in_upper_case() {
+    assert_eq!(Host::default().shifted('é'), 'É');
+}
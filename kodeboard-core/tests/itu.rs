@@ -0,0 +1,150 @@
+//! Goes through every code in ITU-R M.1677, and the common additions to it,
+//! checking that each is decoded as the right character and sent with the
+//! right key on a US keyboard.
+
+use kodeboard_core::code_table::{CodeTable, Decoded};
+use kodeboard_core::decoder::{DecodeError, Decoder, DecoderEvent, Timing};
+use kodeboard_core::layout::{KeyStroke, Layout, SHIFT};
+
+const DIT_MS: u64 = 60;
+
+/// Every code with its character, and the US key code and whether shift is
+/// held to type it
+const CODES: &[(&str, char, u8, bool)] = &[
+    // ITU-R M.1677 letters
+    (".-", 'a', 0x04, false),
+    ("-...", 'b', 0x05, false),
+    ("-.-.", 'c', 0x06, false),
+    ("-..", 'd', 0x07, false),
+    (".", 'e', 0x08, false),
+    ("..-.", 'f', 0x09, false),
+    ("--.", 'g', 0x0A, false),
+    ("....", 'h', 0x0B, false),
+    ("..", 'i', 0x0C, false),
+    (".---", 'j', 0x0D, false),
+    ("-.-", 'k', 0x0E, false),
+    (".-..", 'l', 0x0F, false),
+    ("--", 'm', 0x10, false),
+    ("-.", 'n', 0x11, false),
+    ("---", 'o', 0x12, false),
+    (".--.", 'p', 0x13, false),
+    ("--.-", 'q', 0x14, false),
+    (".-.", 'r', 0x15, false),
+    ("...", 's', 0x16, false),
+    ("-", 't', 0x17, false),
+    ("..-", 'u', 0x18, false),
+    ("...-", 'v', 0x19, false),
+    (".--", 'w', 0x1A, false),
+    ("-..-", 'x', 0x1B, false),
+    ("-.--", 'y', 0x1C, false),
+    ("--..", 'z', 0x1D, false),
+    // ITU-R M.1677 figures
+    (".----", '1', 0x1E, false),
+    ("..---", '2', 0x1F, false),
+    ("...--", '3', 0x20, false),
+    ("....-", '4', 0x21, false),
+    (".....", '5', 0x22, false),
+    ("-....", '6', 0x23, false),
+    ("--...", '7', 0x24, false),
+    ("---..", '8', 0x25, false),
+    ("----.", '9', 0x26, false),
+    ("-----", '0', 0x27, false),
+    // ITU-R M.1677 punctuation
+    (".-.-.-", '.', 0x37, false),
+    ("--..--", ',', 0x36, false),
+    ("---...", ':', 0x33, true),
+    ("..--..", '?', 0x38, true),
+    (".----.", '\'', 0x34, false),
+    ("-....-", '-', 0x2D, false),
+    ("-..-.", '/', 0x38, false),
+    ("-.--.", '(', 0x26, true),
+    ("-.--.-", ')', 0x27, true),
+    (".-..-.", '"', 0x34, true),
+    ("-...-", '=', 0x2E, false),
+    (".-.-.", '+', 0x2E, true),
+    (".--.-.", '@', 0x1F, true),
+    // not in ITU-R M.1677, but in common use
+    ("-.-.--", '!', 0x1E, true),
+    (".-...", '&', 0x24, true),
+    ("-.-.-.", ';', 0x33, false),
+    ("..--.-", '_', 0x2D, true),
+    ("...-..-", '$', 0x21, true),
+];
+
+/// The accented `é` is in ITU-R M.1677 but has no key on a US keyboard
+const E_ACUTE: (&str, char) = ("..-..", 'é');
+
+/// Keys a code with perfect timing and returns the character the decoder makes
+/// of it. `+`, `=` and `(` are decoded as the prosigns that share their codes.
+fn decode(code: &str) -> Result<Option<char>, DecodeError> {
+    let mut decoder = Decoder::new(Timing::new(DIT_MS), 0u64);
+    let mut now = 0;
+    for element in code.chars() {
+        assert!(decoder.push(true, now).all(is_element), "{code}");
+        now += if element == '.' { DIT_MS } else { 3 * DIT_MS };
+        assert!(decoder.push(false, now).all(is_element), "{code}");
+        now += DIT_MS;
+    }
+
+    let mut decoded = None;
+    for event in decoder.push(false, now + 7 * DIT_MS) {
+        match event {
+            DecoderEvent::Character(c) => decoded = Some(c),
+            DecoderEvent::Prosign(prosign) => decoded = prosign.as_char(),
+            DecoderEvent::Error(error) => return Err(error),
+            _ => {}
+        }
+    }
+    Ok(decoded)
+}
+
+fn is_element(event: DecoderEvent) -> bool {
+    matches!(event, DecoderEvent::Element { .. })
+}
+
+#[test]
+fn decodes_every_code() {
+    for &(code, c, _, _) in CODES {
+        assert_eq!(decode(code), Ok(Some(c)), "{code}");
+    }
+
+    let (code, c) = E_ACUTE;
+    assert_eq!(decode(code), Ok(Some(c)), "{code}");
+}
+
+#[test]
+fn decodes_nothing_outside_the_table() {
+    CodeTable::International
+        .tree()
+        .for_each_entry(|code, decoded| {
+            let c = match decoded {
+                Decoded::Char(c) => c,
+                Decoded::Prosign(prosign) => match prosign.as_char() {
+                    Some(c) => c,
+                    None => return,
+                },
+                Decoded::Text(text) => panic!("unexpected text {text}"),
+            };
+            assert!(
+                c == E_ACUTE.1 || CODES.iter().any(|&(_, other, _, _)| other == c),
+                "{code:?} decodes to {c}, which isn't in the table"
+            );
+        });
+}
+
+#[test]
+fn maps_every_character_to_a_us_key() {
+    for &(code, c, key, shift) in CODES {
+        let keys = Layout::Us.keys_for(c).expect("can't type character");
+        let modifier = if shift { SHIFT } else { 0 };
+        assert_eq!(keys.strokes(), [KeyStroke::new(modifier, key)], "{code}");
+    }
+
+    assert!(Layout::Us.keys_for(E_ACUTE.1).is_none());
+}
+
+#[test]
+fn reports_unknown_codes() {
+    assert!(matches!(decode("..--..-"), Err(DecodeError::Unknown(_))));
+    assert!(matches!(decode("--------"), Err(DecodeError::Unknown(_))));
+}
@@ -0,0 +1,143 @@
+use kodeboard_core::code_table::{Decoded, Prosign};
+use kodeboard_core::key_mapping::{
+    ControlKey, KeyAction, ProsignAction, ProsignKeyMap, decoded_to_action, shifted_prosign_action,
+};
+use kodeboard_core::modifiers::Modifier;
+
+#[test]
+fn performs_actions_for_mapped_prosigns() {
+    let keys = ProsignKeyMap::default();
+    let action = |prosign| decoded_to_action(Decoded::Prosign(prosign), &keys);
+
+    assert_eq!(
+        action(Prosign::AR),
+        Some(KeyAction::Control(ControlKey::Enter))
+    );
+    assert_eq!(
+        action(Prosign::BT),
+        Some(KeyAction::Control(ControlKey::Tab))
+    );
+    assert_eq!(
+        action(Prosign::SK),
+        Some(KeyAction::Control(ControlKey::Escape))
+    );
+    assert_eq!(
+        action(Prosign::KN),
+        Some(KeyAction::Control(ControlKey::Backspace))
+    );
+    assert_eq!(action(Prosign::HH), Some(KeyAction::DeleteWord));
+    assert_eq!(
+        action(Prosign::CT),
+        Some(KeyAction::Modifier(Modifier::Ctrl))
+    );
+}
+
+#[test]
+fn types_unmapped_prosigns_as_characters() {
+    let mut keys = ProsignKeyMap::default();
+    keys.set(Prosign::AR, None);
+    keys.set(Prosign::KN, None);
+    keys.set(Prosign::CT, None);
+
+    let action = |prosign| decoded_to_action(Decoded::Prosign(prosign), &keys);
+    assert_eq!(action(Prosign::AR), Some(KeyAction::Type('+')));
+    assert_eq!(action(Prosign::KN), Some(KeyAction::Type('(')));
+    assert_eq!(action(Prosign::SN), None);
+    assert_eq!(action(Prosign::CT), None);
+}
+
+#[test]
+fn types_nothing_for_the_table_switching_prosigns() {
+    let keys = ProsignKeyMap::default();
+    let action = |prosign| decoded_to_action(Decoded::Prosign(prosign), &keys);
+    assert_eq!(action(Prosign::DO), None);
+    assert_eq!(action(Prosign::SN), None);
+}
+
+#[test]
+fn types_prosign_characters_while_shifted() {
+    // AR, BT and KN are the only codes for `+`, `=` and `(`, and the default
+    // map presses keys for all of them, so holding shift is the way to type
+    // them
+    let keys = ProsignKeyMap::default();
+    let action = |prosign| decoded_to_action(Decoded::Prosign(prosign), &keys);
+    assert_eq!(
+        action(Prosign::AR),
+        Some(KeyAction::Control(ControlKey::Enter))
+    );
+    assert_eq!(
+        action(Prosign::BT),
+        Some(KeyAction::Control(ControlKey::Tab))
+    );
+    assert_eq!(
+        action(Prosign::KN),
+        Some(KeyAction::Control(ControlKey::Backspace))
+    );
+
+    assert_eq!(
+        shifted_prosign_action(Prosign::AR),
+        Some(KeyAction::Type('+'))
+    );
+    assert_eq!(
+        shifted_prosign_action(Prosign::BT),
+        Some(KeyAction::Type('='))
+    );
+    assert_eq!(
+        shifted_prosign_action(Prosign::KN),
+        Some(KeyAction::Type('('))
+    );
+    assert_eq!(shifted_prosign_action(Prosign::SK), None);
+    assert_eq!(shifted_prosign_action(Prosign::HH), None);
+}
+
+#[test]
+fn remaps_prosigns() {
+    let mut keys = ProsignKeyMap::default();
+    keys.set(Prosign::KN, Some(ProsignAction::Key(ControlKey::Tab)));
+    keys.set(Prosign::SN, Some(ProsignAction::DeleteCharacter));
+    keys.set(Prosign::DO, Some(ProsignAction::WordModifiers));
+
+    assert_eq!(
+        keys.get(Prosign::KN),
+        Some(ProsignAction::Key(ControlKey::Tab))
+    );
+    assert_eq!(
+        decoded_to_action(Decoded::Prosign(Prosign::KN), &keys),
+        Some(KeyAction::Control(ControlKey::Tab))
+    );
+    assert_eq!(
+        decoded_to_action(Decoded::Prosign(Prosign::SN), &keys),
+        Some(KeyAction::DeleteCharacter)
+    );
+    assert_eq!(
+        decoded_to_action(Decoded::Prosign(Prosign::DO), &keys),
+        Some(KeyAction::WordModifiers)
+    );
+}
+
+#[test]
+fn types_decoded_characters() {
+    let keys = ProsignKeyMap::default();
+    assert_eq!(
+        decoded_to_action(Decoded::Char('k'), &keys),
+        Some(KeyAction::Type('k'))
+    );
+}
+
+#[test]
+fn types_decoded_text() {
+    let keys = ProsignKeyMap::default();
+    assert_eq!(
+        decoded_to_action(Decoded::Text("ch"), &keys),
+        Some(KeyAction::TypeText("ch"))
+    );
+}
+
+#[test]
+fn maps_control_keys() {
+    assert_eq!(ControlKey::Enter.hid_code(), 0x28);
+    assert_eq!(ControlKey::Escape.hid_code(), 0x29);
+    assert_eq!(ControlKey::Backspace.hid_code(), 0x2A);
+    assert_eq!(ControlKey::Tab.hid_code(), 0x2B);
+    assert_eq!(ControlKey::CapsLock.hid_code(), 0x39);
+}
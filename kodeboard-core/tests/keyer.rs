@@ -0,0 +1,122 @@
+use kodeboard_core::keyer::{KeyEdge, Keyer, KeyerConfig, KeyerMode};
+
+const DIT_MS: u64 = 50;
+
+/// Runs the keyer until `end_ms`, updating it every `step_ms` with the paddles
+/// returned by `paddles`. Returns the elements that were sent as dits and dahs.
+fn run(
+    config: KeyerConfig,
+    end_ms: u64,
+    step_ms: u64,
+    paddles: impl Fn(u64) -> (bool, bool),
+) -> String {
+    let mut keyer = Keyer::new(config);
+    let mut sent = String::new();
+    let mut key_down_at = None;
+
+    for now in (0..end_ms).step_by(step_ms as usize) {
+        let (dit, dah) = paddles(now);
+        match keyer.update(now, dit, dah) {
+            Some(KeyEdge { key_down: true, at }) => key_down_at = Some(at),
+            Some(KeyEdge {
+                key_down: false,
+                at,
+            }) => {
+                let length = at - key_down_at.take().expect("key up without key down");
+                sent.push(match length / config.dit_ms {
+                    1 => '.',
+                    3 => '-',
+                    _ => panic!("element was {length}ms long"),
+                });
+            }
+            None => {}
+        }
+    }
+
+    sent
+}
+
+fn config(mode: KeyerMode) -> KeyerConfig {
+    KeyerConfig {
+        mode,
+        ..KeyerConfig::new(DIT_MS)
+    }
+}
+
+#[test]
+fn sends_a_single_element_for_a_tap() {
+    let sent = run(KeyerConfig::new(DIT_MS), 1000, 1, |t| (t < 10, false));
+    assert_eq!(sent, ".");
+
+    let sent = run(KeyerConfig::new(DIT_MS), 1000, 1, |t| (false, t < 10));
+    assert_eq!(sent, "-");
+}
+
+#[test]
+fn repeats_elements_while_a_paddle_is_held() {
+    // a new element starts every 100ms
+    let sent = run(KeyerConfig::new(DIT_MS), 1000, 1, |t| (t < 250, false));
+    assert_eq!(sent, "...");
+
+    // a new element starts every 200ms
+    let sent = run(KeyerConfig::new(DIT_MS), 1000, 1, |t| (false, t < 450));
+    assert_eq!(sent, "---");
+}
+
+#[test]
+fn alternates_elements_when_squeezed() {
+    // the elements start at 0, 100 and 300ms
+    let squeeze = |t| (t < 390, t < 390);
+
+    assert_eq!(run(config(KeyerMode::IambicA), 1000, 1, squeeze), ".-.");
+    // Iambic B sends one more element as the paddles were held during the
+    // last one
+    assert_eq!(run(config(KeyerMode::IambicB), 1000, 1, squeeze), ".-.-");
+}
+
+#[test]
+fn starts_squeezes_with_the_first_paddle() {
+    let squeeze = |t| ((20..190).contains(&t), t < 190);
+    assert_eq!(run(config(KeyerMode::IambicA), 1000, 1, squeeze), "-.");
+}
+
+#[test]
+fn remembers_taps_during_an_element() {
+    // the dit paddle is tapped and released during the dah
+    let paddles = |t| ((50..60).contains(&t), t < 10);
+    assert_eq!(run(config(KeyerMode::IambicA), 1000, 1, paddles), "-.");
+
+    let no_memory = KeyerConfig {
+        memory: false,
+        ..config(KeyerMode::IambicA)
+    };
+    assert_eq!(run(no_memory, 1000, 1, paddles), "-");
+}
+
+#[test]
+fn ultimatic_repeats_the_last_paddle_pressed() {
+    let squeeze = |t| (t < 490, (20..490).contains(&t));
+    assert_eq!(run(config(KeyerMode::Ultimatic), 1000, 1, squeeze), ".--");
+}
+
+#[test]
+fn reversed_paddles_swap_dits_and_dahs() {
+    let reversed = KeyerConfig {
+        reversed: true,
+        ..KeyerConfig::new(DIT_MS)
+    };
+    assert_eq!(run(reversed, 1000, 1, |t| (t < 10, false)), "-");
+    assert_eq!(run(reversed, 1000, 1, |t| (false, t < 10)), ".");
+}
+
+#[test]
+fn keeps_exact_timing_with_late_updates() {
+    // updating every 7ms still sends exact length elements and gaps
+    let mut keyer = Keyer::new(KeyerConfig::new(DIT_MS));
+    let mut edges = Vec::new();
+    for now in (0..420).step_by(7) {
+        edges.extend(keyer.update(now, now < 320, false).map(|edge| edge.at));
+    }
+
+    assert_eq!(edges, [0, 50, 100, 150, 200, 250, 300, 350]);
+}
@@ -0,0 +1,211 @@
+use kodeboard_core::layout::{ALT_GR, Accent, KeyStroke, Layout, SHIFT};
+
+fn key(keycode: u8) -> KeyStroke {
+    KeyStroke::new(0, keycode)
+}
+
+fn shift(keycode: u8) -> KeyStroke {
+    KeyStroke::new(SHIFT, keycode)
+}
+
+fn alt_gr(keycode: u8) -> KeyStroke {
+    KeyStroke::new(ALT_GR, keycode)
+}
+
+/// The key strokes for a character, or an empty list if it can't be typed
+fn keys(layout: Layout, c: char) -> Vec<KeyStroke> {
+    layout
+        .keys_for(c)
+        .map(|keys| keys.strokes().to_vec())
+        .unwrap_or_default()
+}
+
+/// What the host would type for the given key strokes, including composing
+/// characters from dead keys
+fn typed(layout: Layout, strokes: &[KeyStroke]) -> String {
+    let mut text = String::new();
+    let mut dead = None;
+    for &stroke in strokes {
+        if let Some(accent) = layout.dead_key_accent(stroke) {
+            dead = Some(accent);
+            continue;
+        }
+
+        let c = layout.char_for(stroke).expect("key types nothing");
+        match dead.take() {
+            Some(accent) if c == ' ' => text.push(accent.spacing_char()),
+            Some(accent) => text.push(accent.compose(c).expect("nothing to compose")),
+            None => text.push(c),
+        }
+    }
+    text
+}
+
+#[test]
+fn maps_us_letters_and_digits() {
+    assert_eq!(keys(Layout::Us, 'a'), [key(0x04)]);
+    assert_eq!(keys(Layout::Us, 'z'), [key(0x1D)]);
+    assert_eq!(keys(Layout::Us, '1'), [key(0x1E)]);
+    assert_eq!(keys(Layout::Us, '9'), [key(0x26)]);
+    assert_eq!(keys(Layout::Us, '0'), [key(0x27)]);
+    assert_eq!(keys(Layout::Us, ' '), [key(0x2C)]);
+}
+
+#[test]
+fn shifts_us_symbols_on_the_number_row() {
+    assert_eq!(keys(Layout::Us, '!'), [shift(0x1E)]);
+    assert_eq!(keys(Layout::Us, '@'), [shift(0x1F)]);
+    assert_eq!(keys(Layout::Us, '$'), [shift(0x21)]);
+    assert_eq!(keys(Layout::Us, '('), [shift(0x26)]);
+    assert_eq!(keys(Layout::Us, ')'), [shift(0x27)]);
+}
+
+#[test]
+fn shares_keys_between_shifted_and_unshifted_punctuation() {
+    assert_eq!(keys(Layout::Us, '/'), [key(0x38)]);
+    assert_eq!(keys(Layout::Us, '?'), [shift(0x38)]);
+    assert_eq!(keys(Layout::Us, ';'), [key(0x33)]);
+    assert_eq!(keys(Layout::Us, ':'), [shift(0x33)]);
+    assert_eq!(keys(Layout::Us, '\''), [key(0x34)]);
+    assert_eq!(keys(Layout::Us, '"'), [shift(0x34)]);
+}
+
+#[test]
+fn shifts_upper_case_letters() {
+    assert_eq!(keys(Layout::Us, 'A'), [shift(0x04)]);
+    assert_eq!(keys(Layout::De, 'Z'), [shift(0x1C)]);
+    assert_eq!(keys(Layout::Fr, 'Q'), [shift(0x04)]);
+}
+
+#[test]
+fn moves_letters_on_german_and_french_layouts() {
+    assert_eq!(keys(Layout::De, 'y'), [key(0x1D)]);
+    assert_eq!(keys(Layout::De, 'z'), [key(0x1C)]);
+    assert_eq!(keys(Layout::De, 'ö'), [key(0x33)]);
+    assert_eq!(keys(Layout::Fr, 'a'), [key(0x14)]);
+    assert_eq!(keys(Layout::Fr, 'q'), [key(0x04)]);
+    assert_eq!(keys(Layout::Fr, 'm'), [key(0x33)]);
+    assert_eq!(keys(Layout::Fr, 'w'), [key(0x1D)]);
+    assert_eq!(keys(Layout::Fr, 'é'), [key(0x1F)]);
+}
+
+#[test]
+fn shifts_digits_on_the_french_layout() {
+    assert_eq!(keys(Layout::Fr, '1'), [shift(0x1E)]);
+    assert_eq!(keys(Layout::Fr, '0'), [shift(0x27)]);
+    assert_eq!(keys(Layout::Fr, '&'), [key(0x1E)]);
+}
+
+#[test]
+fn uses_alt_gr_for_third_level_characters() {
+    assert_eq!(keys(Layout::De, '@'), [alt_gr(0x14)]);
+    assert_eq!(keys(Layout::De, '€'), [alt_gr(0x08)]);
+    assert_eq!(keys(Layout::Fr, '@'), [alt_gr(0x27)]);
+    assert_eq!(keys(Layout::Uk, '€'), [alt_gr(0x21)]);
+}
+
+#[test]
+fn uses_the_uk_punctuation_keys() {
+    assert_eq!(keys(Layout::Uk, '"'), [shift(0x1F)]);
+    assert_eq!(keys(Layout::Uk, '@'), [shift(0x34)]);
+    assert_eq!(keys(Layout::Uk, '#'), [key(0x32)]);
+    assert_eq!(keys(Layout::Uk, '\\'), [key(0x64)]);
+}
+
+#[test]
+fn moves_letters_on_dvorak_and_colemak_layouts() {
+    assert_eq!(keys(Layout::Dvorak, 'o'), [key(0x16)]);
+    assert_eq!(keys(Layout::Dvorak, 'q'), [key(0x1B)]);
+    assert_eq!(keys(Layout::Dvorak, '-'), [key(0x34)]);
+    assert_eq!(keys(Layout::Colemak, 'r'), [key(0x16)]);
+    assert_eq!(keys(Layout::Colemak, 'k'), [key(0x11)]);
+    assert_eq!(keys(Layout::Colemak, 'a'), [key(0x04)]);
+}
+
+#[test]
+fn types_accented_characters_with_dead_keys() {
+    assert_eq!(keys(Layout::De, 'é'), [key(0x2E), key(0x08)]);
+    assert_eq!(keys(Layout::De, 'É'), [key(0x2E), shift(0x08)]);
+    assert_eq!(keys(Layout::De, 'è'), [shift(0x2E), key(0x08)]);
+    assert_eq!(keys(Layout::De, 'û'), [key(0x35), key(0x18)]);
+    assert_eq!(keys(Layout::Fr, 'ê'), [key(0x2F), key(0x08)]);
+    assert_eq!(keys(Layout::Fr, 'ï'), [shift(0x2F), key(0x0C)]);
+    assert_eq!(keys(Layout::Fr, 'ñ'), [alt_gr(0x1F), key(0x11)]);
+}
+
+#[test]
+fn types_the_accent_of_a_dead_key_with_space() {
+    assert_eq!(keys(Layout::De, '^'), [key(0x35), key(0x2C)]);
+    assert_eq!(keys(Layout::De, '`'), [shift(0x2E), key(0x2C)]);
+    assert_eq!(keys(Layout::Fr, '¨'), [shift(0x2F), key(0x2C)]);
+}
+
+#[test]
+fn rejects_characters_without_a_key() {
+    assert_eq!(Layout::Us.keys_for('é'), None);
+    assert_eq!(Layout::Us.keys_for('€'), None);
+    assert_eq!(Layout::De.keys_for('ñ'), None);
+    assert_eq!(Layout::Fr.keys_for('É'), None);
+}
+
+#[test]
+fn types_every_character_the_decoder_produces() {
+    for layout in Layout::ALL {
+        for c in "abcdefghijklmnopqrstuvwxyz0123456789 .,?'!/()&:;=+-_\"$@".chars() {
+            assert!(layout.keys_for(c).is_some(), "{c} on {layout:?}");
+        }
+    }
+
+    for layout in [Layout::Uk, Layout::De, Layout::Fr] {
+        assert!(layout.keys_for('é').is_some(), "é on {layout:?}");
+    }
+}
+
+#[test]
+fn maps_every_character_of_every_layout_back() {
+    for layout in Layout::ALL {
+        for c in layout.chars() {
+            let strokes = keys(layout, c);
+            assert_eq!(strokes.len(), 1, "{c} on {layout:?}");
+            assert_eq!(typed(layout, &strokes), c.to_string(), "{layout:?}");
+        }
+    }
+}
+
+#[test]
+fn maps_every_dead_key_character_back() {
+    let accents = [
+        Accent::Acute,
+        Accent::Grave,
+        Accent::Circumflex,
+        Accent::Diaeresis,
+        Accent::Tilde,
+    ];
+    for layout in Layout::ALL {
+        for accent in accents {
+            for base in "aeiouynAEIOUYN ".chars() {
+                let c = if base == ' ' {
+                    Some(accent.spacing_char())
+                } else {
+                    accent.compose(base)
+                };
+                let Some(c) = c else { continue };
+                let strokes = keys(layout, c);
+                if !strokes.is_empty() {
+                    assert_eq!(typed(layout, &strokes), c.to_string(), "{layout:?}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn gives_each_key_a_single_character() {
+    for layout in Layout::ALL {
+        let mut chars: Vec<char> = layout.chars().collect();
+        let count = chars.len();
+        chars.sort_unstable();
+        chars.dedup();
+        assert_eq!(chars.len(), count, "{layout:?}");
+    }
+}
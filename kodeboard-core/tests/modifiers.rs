@@ -0,0 +1,200 @@
+use kodeboard_core::layout::{ALT, CTRL, GUI, SHIFT};
+use kodeboard_core::modifiers::{Chord, Gesture, Modifier, ModifierMode, Modifiers, ShiftButton};
+
+#[test]
+fn applies_one_shot_modifiers_to_the_next_character_only() {
+    let mut modifiers = Modifiers::default();
+    assert_eq!(modifiers.pick(Modifier::Ctrl), ModifierMode::OneShot);
+    assert_eq!(modifiers.active(), CTRL);
+
+    assert_eq!(modifiers.take(), CTRL);
+    assert_eq!(modifiers.take(), 0);
+    assert_eq!(modifiers.mode(Modifier::Ctrl), ModifierMode::Off);
+}
+
+#[test]
+fn locks_a_modifier_picked_twice() {
+    let mut modifiers = Modifiers::default();
+    modifiers.pick(Modifier::Gui);
+    assert_eq!(modifiers.pick(Modifier::Gui), ModifierMode::Locked);
+
+    assert_eq!(modifiers.take(), GUI);
+    assert_eq!(modifiers.take(), GUI);
+    modifiers.end_word();
+    assert_eq!(modifiers.take(), GUI);
+
+    assert_eq!(modifiers.pick(Modifier::Gui), ModifierMode::Off);
+    assert_eq!(modifiers.take(), 0);
+}
+
+#[test]
+fn combines_modifiers() {
+    let mut modifiers = Modifiers::default();
+    modifiers.pick(Modifier::Ctrl);
+    modifiers.pick(Modifier::Alt);
+    modifiers.set(Modifier::Shift, ModifierMode::Word);
+
+    assert_eq!(modifiers.take(), CTRL | ALT | SHIFT);
+    assert_eq!(modifiers.take(), SHIFT);
+}
+
+#[test]
+fn leaves_locked_shift_to_caps_lock() {
+    let mut modifiers = Modifiers::default();
+    assert!(!modifiers.caps_lock());
+    modifiers.pick(Modifier::Shift);
+    modifiers.pick(Modifier::Shift);
+    assert!(modifiers.caps_lock());
+    assert_eq!(modifiers.take(), 0);
+
+    modifiers.pick(Modifier::Ctrl);
+    assert_eq!(modifiers.take(), CTRL);
+}
+
+#[test]
+fn follows_the_hosts_caps_lock() {
+    let mut modifiers = Modifiers::default();
+    modifiers.sync_caps_lock(true);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Locked);
+    modifiers.sync_caps_lock(false);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Off);
+
+    // shift for the next character isn't affected by Caps Lock going off
+    modifiers.pick(Modifier::Shift);
+    modifiers.sync_caps_lock(false);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::OneShot);
+}
+
+#[test]
+fn keeps_word_modifiers_until_the_end_of_the_word() {
+    let mut modifiers = Modifiers::default();
+    modifiers.shift_gesture(Gesture::LongPress);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Word);
+
+    assert_eq!(modifiers.take(), SHIFT);
+    assert_eq!(modifiers.take(), SHIFT);
+    modifiers.end_word();
+    assert_eq!(modifiers.take(), 0);
+}
+
+#[test]
+fn keeps_one_shot_modifiers_until_the_end_of_the_word() {
+    let mut modifiers = Modifiers::default();
+    modifiers.pick(Modifier::Ctrl);
+    modifiers.pick(Modifier::Alt);
+    modifiers.pick(Modifier::Alt);
+    modifiers.extend_to_word();
+    assert_eq!(modifiers.mode(Modifier::Ctrl), ModifierMode::Word);
+    // locked modifiers stay locked
+    assert_eq!(modifiers.mode(Modifier::Alt), ModifierMode::Locked);
+
+    assert_eq!(modifiers.take(), CTRL | ALT);
+    assert_eq!(modifiers.take(), CTRL | ALT);
+    modifiers.end_word();
+    assert_eq!(modifiers.take(), ALT);
+}
+
+#[test]
+fn turns_off_word_modifiers_when_picked() {
+    let mut modifiers = Modifiers::default();
+    modifiers.set(Modifier::Shift, ModifierMode::Word);
+    assert_eq!(modifiers.pick(Modifier::Shift), ModifierMode::Off);
+}
+
+#[test]
+fn taps_pick_shift() {
+    let mut modifiers = Modifiers::default();
+    modifiers.shift_gesture(Gesture::Tap);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::OneShot);
+    modifiers.shift_gesture(Gesture::Tap);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Locked);
+    modifiers.shift_gesture(Gesture::Tap);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Off);
+}
+
+#[test]
+fn double_taps_undo_the_first_tap() {
+    let mut modifiers = Modifiers::default();
+    modifiers.set(Modifier::Shift, ModifierMode::Locked);
+    modifiers.shift_gesture(Gesture::Tap);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Off);
+    modifiers.shift_gesture(Gesture::DoubleTap);
+    assert_eq!(modifiers.mode(Modifier::Shift), ModifierMode::Locked);
+}
+
+#[test]
+fn picks_modifiers_by_letter() {
+    assert_eq!(Modifier::for_letter('c'), Some(Modifier::Ctrl));
+    assert_eq!(Modifier::for_letter('S'), Some(Modifier::Shift));
+    assert_eq!(Modifier::for_letter('a'), Some(Modifier::Alt));
+    assert_eq!(Modifier::for_letter('g'), Some(Modifier::Gui));
+    assert_eq!(Modifier::for_letter('x'), None);
+}
+
+#[test]
+fn chords_letters_with_the_shift_button() {
+    assert_eq!(Chord::for_letter('g'), Some(Chord::Pick(Modifier::Gui)));
+    assert_eq!(Chord::for_letter('w'), Some(Chord::Word));
+    assert_eq!(Chord::for_letter('W'), Some(Chord::Word));
+    assert_eq!(Chord::for_letter('x'), None);
+}
+
+#[test]
+fn recognises_taps_on_release() {
+    let mut shift = ShiftButton::<u64>::default();
+    assert_eq!(shift.update(true, 0), None);
+    assert!(shift.is_held());
+    assert_eq!(shift.update(true, 50), None);
+    assert_eq!(shift.update(false, 100), Some(Gesture::Tap));
+    assert!(!shift.is_held());
+    assert_eq!(shift.update(false, 150), None);
+}
+
+#[test]
+fn recognises_double_taps() {
+    let mut shift = ShiftButton::<u64>::default();
+    shift.update(true, 0);
+    assert_eq!(shift.update(false, 100), Some(Gesture::Tap));
+    shift.update(true, 300);
+    assert_eq!(shift.update(false, 400), Some(Gesture::DoubleTap));
+
+    // a third tap starts again
+    shift.update(true, 500);
+    assert_eq!(shift.update(false, 600), Some(Gesture::Tap));
+}
+
+#[test]
+fn slow_taps_are_separate() {
+    let mut shift = ShiftButton::<u64>::default();
+    shift.update(true, 0);
+    assert_eq!(shift.update(false, 100), Some(Gesture::Tap));
+    shift.update(true, 800);
+    assert_eq!(shift.update(false, 900), Some(Gesture::Tap));
+}
+
+#[test]
+fn recognises_long_presses() {
+    let mut shift = ShiftButton::<u64>::default();
+    shift.update(true, 0);
+    assert_eq!(shift.update(false, 1000), Some(Gesture::LongPress));
+}
+
+#[test]
+fn chords_are_not_gestures() {
+    let mut shift = ShiftButton::<u64>::default();
+    shift.update(true, 0);
+    shift.chord();
+    assert_eq!(shift.update(false, 1000), None);
+
+    // and don't count towards a double tap
+    shift.update(true, 1100);
+    assert_eq!(shift.update(false, 1200), Some(Gesture::Tap));
+}
+
+#[test]
+fn ignores_chords_while_released() {
+    let mut shift = ShiftButton::<u64>::default();
+    shift.chord();
+    shift.update(true, 0);
+    assert_eq!(shift.update(false, 100), Some(Gesture::Tap));
+}
@@ -0,0 +1,174 @@
+use kodeboard_core::host::Host;
+use kodeboard_core::layout::{ALT, CTRL, KeyStroke, Layout, SHIFT};
+use kodeboard_core::unicode::UnicodeEntry;
+
+fn key(keycode: u8) -> KeyStroke {
+    KeyStroke::new(0, keycode)
+}
+
+fn shift(keycode: u8) -> KeyStroke {
+    KeyStroke::new(SHIFT, keycode)
+}
+
+fn keypad(digit: u8) -> KeyStroke {
+    match digit {
+        0 => key(0x62),
+        _ => key(0x58 + digit),
+    }
+}
+
+/// The held modifiers and key strokes that enter a character
+fn entry(mode: UnicodeEntry, layout: Layout, c: char) -> (u8, Vec<KeyStroke>) {
+    let keys = mode.keys_for(c, layout).expect("can't enter character");
+    (keys.held(), keys.strokes().to_vec())
+}
+
+#[test]
+fn enters_hex_with_ctrl_shift_u_on_linux() {
+    let (held, strokes) = entry(UnicodeEntry::Linux, Layout::Us, 'é');
+    assert_eq!(held, 0);
+    assert_eq!(
+        strokes,
+        [
+            KeyStroke::new(CTRL | SHIFT, 0x18),
+            key(0x08),
+            key(0x26),
+            key(0x2C)
+        ]
+    );
+}
+
+#[test]
+fn uses_the_host_layout_for_hex_digits_on_linux() {
+    let (_, strokes) = entry(UnicodeEntry::Linux, Layout::Fr, 'é');
+    assert_eq!(
+        strokes,
+        [
+            KeyStroke::new(CTRL | SHIFT, 0x18),
+            key(0x08),
+            shift(0x26),
+            key(0x2C)
+        ]
+    );
+
+    let (_, strokes) = entry(UnicodeEntry::Linux, Layout::Dvorak, 'Ж');
+    assert_eq!(
+        strokes,
+        [
+            KeyStroke::new(CTRL | SHIFT, 0x09),
+            key(0x21),
+            key(0x1E),
+            key(0x23),
+            key(0x2C)
+        ]
+    );
+}
+
+#[test]
+fn enters_decimal_alt_codes_on_windows() {
+    let (held, strokes) = entry(UnicodeEntry::WindowsAltCode, Layout::Us, 'Ж');
+    assert_eq!(held, ALT);
+    assert_eq!(strokes, [keypad(1), keypad(0), keypad(4), keypad(6)]);
+}
+
+#[test]
+fn adds_a_leading_zero_to_small_alt_codes() {
+    let (_, strokes) = entry(UnicodeEntry::WindowsAltCode, Layout::Us, 'é');
+    assert_eq!(strokes, [keypad(0), keypad(2), keypad(3), keypad(3)]);
+}
+
+#[test]
+fn enters_hex_on_the_numpad_on_windows() {
+    let (held, strokes) = entry(UnicodeEntry::WindowsHexNumpad, Layout::Us, 'ñ');
+    assert_eq!(held, ALT);
+    assert_eq!(strokes, [key(0x57), key(0x09), keypad(1)]);
+
+    let (_, strokes) = entry(UnicodeEntry::WindowsHexNumpad, Layout::Dvorak, 'ñ');
+    assert_eq!(strokes, [key(0x57), key(0x1C), keypad(1)]);
+}
+
+#[test]
+fn enters_four_hex_digits_with_option_on_macos() {
+    let (held, strokes) = entry(UnicodeEntry::MacOs, Layout::Us, 'é');
+    assert_eq!(held, ALT);
+    assert_eq!(strokes, [key(0x27), key(0x27), key(0x08), key(0x26)]);
+}
+
+#[test]
+fn uses_us_key_positions_on_macos() {
+    for layout in Layout::ALL {
+        let (_, strokes) = entry(UnicodeEntry::MacOs, layout, 'é');
+        assert_eq!(strokes, [key(0x27), key(0x27), key(0x08), key(0x26)]);
+    }
+}
+
+#[test]
+fn enters_surrogate_pairs_on_macos() {
+    let (_, strokes) = entry(UnicodeEntry::MacOs, Layout::Us, '😀');
+    // D83D DE00
+    assert_eq!(
+        strokes,
+        [
+            key(0x07),
+            key(0x25),
+            key(0x20),
+            key(0x07),
+            key(0x07),
+            key(0x08),
+            key(0x27),
+            key(0x27)
+        ]
+    );
+}
+
+#[test]
+fn enters_every_code_point_on_every_layout() {
+    for mode in UnicodeEntry::ALL {
+        for layout in Layout::ALL {
+            for c in ['a', 'é', 'Ж', 'ש', '\u{FFFF}', '😀', '\u{10FFFF}'] {
+                assert!(
+                    mode.keys_for(c, layout).is_some(),
+                    "{c} {mode:?} {layout:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn maps_hex_digits_back_on_every_layout() {
+    for layout in Layout::ALL {
+        let (_, strokes) = entry(UnicodeEntry::Linux, layout, '\u{10ABCD}');
+        let hex: String = strokes[1..strokes.len() - 1]
+            .iter()
+            .map(|&stroke| layout.char_for(stroke).unwrap())
+            .collect();
+        assert_eq!(hex, "10abcd", "{layout:?}");
+    }
+}
+
+#[test]
+fn only_enters_code_points_for_characters_missing_from_the_layout() {
+    let host = Host {
+        layout: Layout::Us,
+        unicode_entry: Some(UnicodeEntry::Linux),
+    };
+    assert_eq!(host.keys_for('e').unwrap().strokes(), [key(0x08)]);
+    assert_eq!(host.keys_for('é').unwrap().strokes().len(), 4);
+
+    let host = Host {
+        layout: Layout::De,
+        unicode_entry: Some(UnicodeEntry::Linux),
+    };
+    assert_eq!(
+        host.keys_for('é').unwrap().strokes(),
+        [key(0x2E), key(0x08)]
+    );
+}
+
+#[test]
+fn skips_missing_characters_without_an_entry_mode() {
+    let host = Host::default();
+    assert_eq!(host.keys_for('é'), None);
+    assert!(host.keys_for('e').is_some());
+}
@@ -0,0 +1,275 @@
+use kodeboard_core::code_table::{Element, MorseCode};
+use kodeboard_core::decoder::{Decoder, DecoderEvent, Timing};
+use kodeboard_core::layout::{CTRL, KeyStroke, SHIFT};
+use kodeboard_core::user_table::{
+    MAX_ENTRIES, MAX_TABLE_LEN, MacroStep, TableError, TableWriter, UserAction, UserTable,
+};
+
+const COPY: KeyStroke = KeyStroke::new(CTRL, 0x06);
+
+/// Writes a table with a text entry, a key entry and a macro entry
+fn write_table(buf: &mut [u8]) -> UserTable<'_> {
+    let mut writer = TableWriter::new(buf);
+    writer
+        .push_text(&MorseCode::from_str("..--.-.."), "73 de M0ABC")
+        .unwrap();
+    writer
+        .push_key(&MorseCode::from_str("-.-.-.."), COPY)
+        .unwrap();
+    writer
+        .push_macro(
+            &MorseCode::from_str(".-.-..-"),
+            &[
+                MacroStep::Key(KeyStroke::new(SHIFT, 0x4A)),
+                MacroStep::Delay(250),
+                MacroStep::Text("Grüße"),
+            ],
+        )
+        .unwrap();
+    writer.finish().unwrap()
+}
+
+#[test]
+fn looks_up_each_kind_of_action() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let table = write_table(&mut buf);
+    assert_eq!(table.len(), 3);
+
+    assert_eq!(
+        table.lookup(&MorseCode::from_str("..--.-..")),
+        Some(UserAction::Text("73 de M0ABC"))
+    );
+    assert_eq!(
+        table.lookup(&MorseCode::from_str("-.-.-..")),
+        Some(UserAction::Key(COPY))
+    );
+    let Some(UserAction::Macro(steps)) = table.lookup(&MorseCode::from_str(".-.-..-")) else {
+        panic!("expected a macro");
+    };
+    assert_eq!(
+        steps.collect::<Vec<_>>(),
+        [
+            MacroStep::Key(KeyStroke::new(SHIFT, 0x4A)),
+            MacroStep::Delay(250),
+            MacroStep::Text("Grüße"),
+        ]
+    );
+    assert_eq!(table.lookup(&MorseCode::from_str(".-")), None);
+}
+
+#[test]
+fn parses_a_stored_table() {
+    let mut buf = [0xFF; MAX_TABLE_LEN];
+    let written = write_table(&mut buf);
+    let len = written.as_bytes().len();
+
+    // the rest of the flash sector is left erased
+    let table = UserTable::parse(&buf).unwrap();
+    assert_eq!(table.as_bytes().len(), len);
+    assert_eq!(
+        table.codes().as_slice(),
+        [
+            MorseCode::from_str("..--.-.."),
+            MorseCode::from_str("-.-.-.."),
+            MorseCode::from_str(".-.-..-"),
+        ]
+    );
+}
+
+#[test]
+fn rejects_erased_flash() {
+    assert_eq!(
+        UserTable::parse(&[0xFF; MAX_TABLE_LEN]),
+        Err(TableError::BadHeader)
+    );
+    assert_eq!(UserTable::parse(&[]), Err(TableError::BadHeader));
+}
+
+#[test]
+fn rejects_corrupted_tables() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let len = write_table(&mut buf).as_bytes().len();
+
+    let mut corrupted = buf;
+    corrupted[20] ^= 0x01;
+    assert_eq!(UserTable::parse(&corrupted), Err(TableError::BadChecksum));
+
+    assert_eq!(
+        UserTable::parse(&buf[..len - 1]),
+        Err(TableError::Truncated)
+    );
+}
+
+#[test]
+fn rejects_malformed_entries() {
+    let mut buf = [0; 64];
+    let mut writer = TableWriter::new(&mut buf);
+    writer.push_text(&MorseCode::from_str("...-"), "v").unwrap();
+    let len = writer.finish().unwrap().as_bytes().len();
+
+    // claim there are two entries
+    let mut extra_entry = buf;
+    extra_entry[5] = 2;
+    assert_eq!(
+        UserTable::parse(&extra_entry[..len]),
+        Err(TableError::Malformed(1))
+    );
+
+    // claim there are none
+    let mut missing_entry = buf;
+    missing_entry[5] = 0;
+    assert_eq!(
+        UserTable::parse(&missing_entry[..len]),
+        Err(TableError::Malformed(0))
+    );
+}
+
+#[test]
+fn rejects_duplicate_codes() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let mut writer = TableWriter::new(&mut buf);
+    writer
+        .push_text(&MorseCode::from_str("..--"), "one")
+        .unwrap();
+    writer
+        .push_text(&MorseCode::from_str("--.."), "two")
+        .unwrap();
+    writer
+        .push_text(&MorseCode::from_str("..--"), "three")
+        .unwrap();
+    assert_eq!(
+        writer.finish(),
+        Err(TableError::DuplicateCode {
+            first: 0,
+            second: 2
+        })
+    );
+}
+
+#[test]
+fn rejects_codes_that_start_other_codes() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let mut writer = TableWriter::new(&mut buf);
+    writer
+        .push_text(&MorseCode::from_str("..--..--"), "long")
+        .unwrap();
+    writer
+        .push_text(&MorseCode::from_str("..--"), "short")
+        .unwrap();
+    assert_eq!(
+        writer.finish(),
+        Err(TableError::PrefixConflict { prefix: 1, code: 0 })
+    );
+}
+
+#[test]
+fn stops_writing_when_the_buffer_is_full() {
+    let mut buf = [0; 32];
+    let mut writer = TableWriter::new(&mut buf);
+    writer
+        .push_text(&MorseCode::from_str("..--"), "fits")
+        .unwrap();
+    assert_eq!(
+        writer.push_text(&MorseCode::from_str("--.."), "doesn't fit"),
+        Err(TableError::TooBig)
+    );
+
+    // the entry that didn't fit is left out
+    let table = writer.finish().unwrap();
+    assert_eq!(table.len(), 1);
+}
+
+#[test]
+fn limits_the_number_of_entries() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let mut writer = TableWriter::new(&mut buf);
+    for i in 0..MAX_ENTRIES {
+        // six elements from the bits of the index, so no code starts another
+        let mut code = MorseCode::new();
+        for bit in 0..6 {
+            code.push(if i >> bit & 1 == 1 {
+                Element::Dah
+            } else {
+                Element::Dit
+            });
+        }
+        writer.push_text(&code, "x").unwrap();
+    }
+    assert_eq!(
+        writer.push_text(&MorseCode::from_str("-------"), "x"),
+        Err(TableError::TooBig)
+    );
+    assert_eq!(writer.finish().unwrap().len(), MAX_ENTRIES);
+}
+
+#[test]
+fn rejects_empty_codes_and_long_text() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let mut writer = TableWriter::new(&mut buf);
+    assert_eq!(
+        writer.push_text(&MorseCode::new(), "x"),
+        Err(TableError::Malformed(0))
+    );
+    let long = "x".repeat(256);
+    assert_eq!(
+        writer.push_text(&MorseCode::from_str("..--"), &long),
+        Err(TableError::Malformed(0))
+    );
+    assert_eq!(
+        writer.push_macro(&MorseCode::from_str("..--"), &[MacroStep::Text(&long)]),
+        Err(TableError::Malformed(0))
+    );
+}
+
+#[test]
+fn decoder_leaves_user_codes_to_the_table() {
+    let mut buf = [0; MAX_TABLE_LEN];
+    let mut writer = TableWriter::new(&mut buf);
+    // replaces `a`
+    writer
+        .push_text(&MorseCode::from_str(".-"), "alpha")
+        .unwrap();
+    writer
+        .push_text(&MorseCode::from_str("..--.-.."), "73")
+        .unwrap();
+    let table = writer.finish().unwrap();
+
+    let mut decoder = Decoder::new(Timing::new(60), 0);
+    decoder.set_user_codes(table.codes());
+
+    // `.-`, `..--.-..` and then `e`, each followed by a letter gap
+    let mut now = 0;
+    let mut events = Vec::new();
+    for code in [".-", "..--.-..", "."] {
+        for element in code.chars() {
+            let length = if element == '.' { 60 } else { 180 };
+            for (key_down, duration) in [(true, length), (false, 60)] {
+                for _ in 0..duration {
+                    now += 1;
+                    events.extend(decoder.push(key_down, now));
+                }
+            }
+        }
+        for _ in 0..120 {
+            now += 1;
+            events.extend(decoder.push(false, now));
+        }
+    }
+
+    let found: Vec<_> = events
+        .into_iter()
+        .filter(|event| !matches!(event, DecoderEvent::Element { .. }))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            DecoderEvent::UserCode(MorseCode::from_str(".-")),
+            DecoderEvent::UserCode(MorseCode::from_str("..--.-..")),
+            DecoderEvent::Character('e'),
+        ]
+    );
+    assert_eq!(
+        table.lookup(&MorseCode::from_str(".-")),
+        Some(UserAction::Text("alpha"))
+    );
+}
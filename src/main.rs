@@ -1,39 +1,95 @@
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use defmt::*;
 use embassy_executor::Spawner;
+use embassy_futures::select::select;
 use embassy_rp::bind_interrupts;
-use embassy_rp::gpio::{Input, Pull};
-use embassy_rp::peripherals::USB;
+use embassy_rp::flash::{Blocking, ERASE_SIZE, Flash};
+use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::peripherals::{FLASH, USB};
 use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_sync::blocking_mutex;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
-use embassy_sync::channel::{Channel, Receiver, Sender};
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Instant, Ticker, Timer};
-use embassy_usb::class::hid::{HidReader, HidReaderWriter, HidWriter, State};
+use embassy_usb::driver::{Endpoint, EndpointError, EndpointIn, EndpointOut};
 use embassy_usb::msos::windows_version;
 use embassy_usb::{Builder, Config, UsbDevice};
-use key_mapping::char_to_hid_u8;
+use kodeboard_core::buffer::{EventBuffer, OverflowPolicy};
+use kodeboard_core::code_table::{CodeTable, Decoded, MorseCode, Prosign};
+use kodeboard_core::decoder::{DecoderEvent, ErrorPolicy};
+use kodeboard_core::hid::{HidState, KeyReport, NKRO_REPORT_DESCRIPTOR, ReportQueue};
+use kodeboard_core::history::TypedHistory;
+use kodeboard_core::host::{Host, HostLeds};
+use kodeboard_core::key_mapping::{
+    ControlKey, KeyAction, ProsignKeyMap, decoded_to_action, shifted_prosign_action,
+};
+use kodeboard_core::keyer::KeyerConfig;
+use kodeboard_core::layout::{KeySequence, KeyStroke, Layout, SHIFT};
+use kodeboard_core::modifiers::{Chord, Gesture, Modifier, Modifiers, ShiftButton};
+use kodeboard_core::user_table::{
+    MAX_TABLE_LEN, MacroStep, TableError, UserAction, UserCodes, UserTable,
+};
+use kodeboard_core::{debouncer, decoder, keyer};
 use static_cell::StaticCell;
-use usb::KodeboardUsbDeviceHandler;
+use usb::{
+    HidStateMutex, HostLedsSignal, KodeboardUsbDeviceHandler, KodeboardUsbRequestHandler,
+    UserTableSignal, WakeSignal,
+};
 use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
 use {defmt_rtt as _, panic_probe as _};
 
-mod debouncer;
-mod decoder;
-mod key_mapping;
 mod usb;
 
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => InterruptHandler<USB>;
 });
 
-type EventChannelType = (char, bool);
-type EventChannel = Channel<ThreadModeRawMutex, EventChannelType, 32>;
-type EventSender = Sender<'static, ThreadModeRawMutex, EventChannelType, 32>;
-type EventReceiver = Receiver<'static, ThreadModeRawMutex, EventChannelType, 32>;
-static EVENT_CHANNEL: EventChannel = Channel::new();
+/// Something for the USB HID task to send to the host
+#[derive(Clone, Copy, Format)]
+enum HidEvent {
+    /// A decoded character or prosign, which is typed with the modifiers, and
+    /// whether the shift button was held
+    Decoded(Decoded, bool),
+    /// An action that doesn't depend on the prosign mappings or modifiers
+    Action(KeyAction),
+    /// A code from the user code table
+    User(MorseCode),
+    /// A gesture on the shift button, which changes the modifiers
+    ShiftGesture(Gesture),
+    /// The space after a word, which ends the word without using up a one-shot
+    /// modifier
+    WordGap,
+}
+
+/// The most events that can wait for the USB HID task
+const EVENT_CAPACITY: usize = 64;
+
+// Change this to keep the newest events instead when the host falls behind
+const EVENT_OVERFLOW: OverflowPolicy = OverflowPolicy::DropNewest;
+
+// The events waiting for the USB HID task. The buttons are read and decoded
+// without ever waiting for this, so a slow or stalled host can't warp the
+// timing of what is being sent.
+type EventBufferMutex =
+    blocking_mutex::Mutex<ThreadModeRawMutex, RefCell<EventBuffer<HidEvent, EVENT_CAPACITY>>>;
+static EVENTS: EventBufferMutex =
+    EventBufferMutex::new(RefCell::new(EventBuffer::new(EVENT_OVERFLOW)));
+
+/// Passes an event on to the USB HID task without waiting, dropping it or an
+/// older one if the buffer is full
+fn send_event(event: HidEvent) {
+    let queued = EVENTS.lock(|events| events.borrow_mut().push(event));
+    if !queued {
+        debug!("The event buffer is full");
+    }
+    HID_WAKE.signal(());
+}
 
 // Descriptors for the USB. Static so we can share the USB handles around tasks
 static CONFIG_DESC: StaticCell<[u8; 256]> = StaticCell::new();
@@ -41,11 +97,87 @@ static BOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
 static MSOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
 static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
 
-// The state for the USB
-static STATE: StaticCell<State> = StaticCell::new();
-
 // The USB device handler
 static USB_DEV_HANDLER: StaticCell<KodeboardUsbDeviceHandler> = StaticCell::new();
+// The handler for HID class requests on the control endpoint
+static HID_REQUEST_HANDLER: StaticCell<KodeboardUsbRequestHandler> = StaticCell::new();
+// The HID protocol, idle rate and last report
+static HID_STATE: HidStateMutex = HidStateMutex::new(RefCell::new(HidState::new(NKRO)));
+// Wakes the USB HID task for new events, LEDs or idle rates
+static HID_WAKE: WakeSignal = Signal::new();
+
+/// The size of the flash chip on the Pico
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// Where the user code table is stored. This is the last sector of flash, which
+/// memory.x keeps the firmware out of.
+const USER_TABLE_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
+const _: () = core::assert!(MAX_TABLE_LEN <= ERASE_SIZE);
+
+// The user code table, which is loaded from flash and can be replaced over USB.
+// Until a valid table is loaded this is all zeros, which doesn't parse.
+static USER_TABLE: Mutex<ThreadModeRawMutex, [u8; MAX_TABLE_LEN]> = Mutex::new([0; MAX_TABLE_LEN]);
+// User code tables that have been uploaded and checked, waiting to be stored
+static USER_TABLE_UPLOADS: UserTableSignal = Signal::new();
+// The codes in the user code table, for the decoder to leave to the table
+static USER_CODES: Signal<ThreadModeRawMutex, UserCodes> = Signal::new();
+// The keyboard LEDs from the host's output reports
+static HOST_LEDS: HostLedsSignal = Signal::new();
+// Whether a user code table is being written to flash. Nothing else runs while
+// the flash is erased and written, so the buttons aren't read and the decoder
+// is reset afterwards.
+static STORING_USER_TABLE: AtomicBool = AtomicBool::new(false);
+
+/// How the morse buttons are used
+#[derive(Clone, Copy)]
+enum InputMode {
+    /// A single straight key on the morse button
+    StraightKey,
+    /// A dual-lever paddle, with the dit paddle on the morse button and the dah
+    /// paddle on the space button. Word gaps are used to type spaces instead.
+    Paddles(KeyerConfig),
+}
+
+// Change this to `Some(KeyerConfig::new(60))`, or another keyer config, to use
+// a paddle instead of a straight key
+const PADDLES: Option<KeyerConfig> = None;
+
+const INPUT_MODE: InputMode = match PADDLES {
+    Some(config) => InputMode::Paddles(config),
+    None => InputMode::StraightKey,
+};
+
+// Change this to `Some((character_wpm, effective_wpm))` for Farnsworth timing,
+// e.g. `Some((18.0, 10.0))` for learners who send each character at 18 WPM but
+// leave longer gaps between them
+const FARNSWORTH: Option<(f32, f32)> = None;
+
+// Change this to type something when a morse sequence can't be decoded
+const ERROR_POLICY: ErrorPolicy = ErrorPolicy::Ignore;
+
+// Change this to decode a national morse code table, or American Morse, instead.
+// Japanese Wabun can also be switched to with the DO prosign and back again
+// with SN.
+const CODE_TABLE: CodeTable = CodeTable::International;
+
+// Cut numbers (e.g. `5NN` for `599`) are typed as digits after a double tap on
+// the shift button, until the end of the next word. The double tap leaves shift
+// as it was before the first tap. Change this to also toggle them with a
+// prosign, e.g. `Some(Prosign::KN)`. The prosign then only toggles them, so KN
+// would no longer press Backspace.
+const CUT_NUMBERS_PROSIGN: Option<Prosign> = None;
+
+// Change this to send NKRO bitmap reports, for hosts that support them. The
+// boot keyboard report is still sent to hosts that ask for the boot protocol.
+const NKRO: bool = false;
+
+// Change this to match the keyboard layout the host is set to, and how it
+// accepts Unicode code points for characters that aren't on that layout, e.g.
+// `Some(UnicodeEntry::Linux)`
+const HOST: Host = Host {
+    layout: Layout::Us,
+    unicode_entry: None,
+};
 
 type ButtonType = Mutex<ThreadModeRawMutex, Option<Input<'static>>>;
 static MORSE_BUTTON: ButtonType = Mutex::new(None);
@@ -82,9 +214,20 @@ async fn main(spawner: Spawner) {
 
     let p = embassy_rp::init(Default::default());
 
+    // Load the user code table
+    let mut flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(p.FLASH);
+    let mut stored_table = [0; MAX_TABLE_LEN];
+    match flash.blocking_read(USER_TABLE_OFFSET, &mut stored_table) {
+        Ok(()) => load_user_table(&stored_table).await,
+        Err(e) => warn!("Unable to read the user code table: {}", e),
+    }
+
     // Set up USB
     let driver = Driver::new(p.USB, Irqs);
-    let device_handler = USB_DEV_HANDLER.init(usb::KodeboardUsbDeviceHandler::default());
+    let device_handler = USB_DEV_HANDLER.init(KodeboardUsbDeviceHandler::new(
+        &USER_TABLE_UPLOADS,
+        &HID_STATE,
+    ));
 
     // TODO: this is a test code from pid.codes, change before release
     let mut config = Config::new(0x16c0, 0x27dd);
@@ -105,16 +248,26 @@ async fn main(spawner: Spawner) {
     builder.handler(device_handler);
     builder.msos_descriptor(windows_version::WIN10, 2);
 
-    // Create the HID inteface
-    let hid_config = embassy_usb::class::hid::Config {
-        report_descriptor: KeyboardReport::desc(),
-        request_handler: None,
-        poll_ms: 60,
-        max_packet_size: 64,
+    // Create the HID inteface. It is a boot keyboard, so it works in BIOS setup
+    // screens and bootloaders too.
+    let report_descriptor = if NKRO {
+        NKRO_REPORT_DESCRIPTOR
+    } else {
+        KeyboardReport::desc()
     };
-    let hid = HidReaderWriter::<_, 1, 8>::new(&mut builder, STATE.init(State::new()), hid_config);
+    let request_handler = HID_REQUEST_HANDLER.init(KodeboardUsbRequestHandler::new(
+        report_descriptor,
+        &HOST_LEDS,
+        &HID_STATE,
+        &HID_WAKE,
+    ));
+    // poll often, as each report is sent on its own poll
+    let (writer, reader) = request_handler.add_interface(&mut builder, 1);
     let usb = builder.build();
 
+    // The onboard LED shows the host's Caps Lock
+    let caps_lock_led = Output::new(p.PIN_25, Level::Low);
+
     // Set up the button for listening to morse code inputs
     setup_button!(p.PIN_14, SPACE_BUTTON);
     setup_button!(p.PIN_15, SHIFT_BUTTON);
@@ -127,20 +280,27 @@ async fn main(spawner: Spawner) {
     unwrap!(spawner.spawn(usb_loop(usb)));
 
     info!("Spawning usb HID transmission task");
-    let (reader, writer) = hid.split();
-    unwrap!(spawner.spawn(usb_hid_loop(EVENT_CHANNEL.receiver(), writer)));
+    // Change the prosign mappings here to send different control keys or pick
+    // modifiers, e.g. `ProsignAction::Modifier(Modifier::Gui)`
+    let prosign_keys = ProsignKeyMap::default();
+    unwrap!(spawner.spawn(usb_hid_loop(writer, prosign_keys, HOST, caps_lock_led)));
 
-    info!("Spawning USB request handler task");
-    unwrap!(spawner.spawn(usb_request_handler(reader)));
+    info!("Spawning USB output report task");
+    unwrap!(spawner.spawn(read_output_reports(reader)));
 
-    info!("Spawning space bar monitoring task");
-    unwrap!(spawner.spawn(monitor_space_key(&SPACE_BUTTON, EVENT_CHANNEL.sender())));
+    info!("Spawning user code table storage task");
+    unwrap!(spawner.spawn(store_user_tables(flash)));
+
+    if let InputMode::StraightKey = INPUT_MODE {
+        info!("Spawning space bar monitoring task");
+        unwrap!(spawner.spawn(monitor_space_key(&SPACE_BUTTON)));
+    }
 
     info!("Spawning morse code button observer task");
     unwrap!(spawner.spawn(generate_morse_code_characters(
         &MORSE_BUTTON,
-        &SHIFT_BUTTON,
-        EVENT_CHANNEL.sender()
+        &SPACE_BUTTON,
+        &SHIFT_BUTTON
     )));
 }
 
@@ -150,76 +310,430 @@ async fn usb_loop(mut usb: UsbDevice<'static, Driver<'static, USB>>) -> ! {
     usb.run().await
 }
 
-/// Listens for events from the morse code parser and sends them on as key
-/// presses on the HID keyboard interface
+/// The endpoint that input reports are sent on
+type KeyboardWriter = <Driver<'static, USB> as embassy_usb::driver::Driver<'static>>::EndpointIn;
+/// The endpoint that output reports are received on
+type KeyboardReader = <Driver<'static, USB> as embassy_usb::driver::Driver<'static>>::EndpointOut;
+
+/// The HID keyboard interface, and the reports waiting to be sent on it
+struct Keyboard {
+    writer: KeyboardWriter,
+    queue: ReportQueue,
+}
+
+impl Keyboard {
+    fn new(writer: KeyboardWriter) -> Self {
+        Self {
+            writer,
+            queue: ReportQueue::default(),
+        }
+    }
+
+    /// Sends a report in the format the host is using, and remembers it for
+    /// GET_REPORT and the idle rate
+    async fn write_report(&mut self, report: KeyReport) {
+        let encoded = HID_STATE.lock(|hid| {
+            let mut hid = hid.borrow_mut();
+            hid.sent(report, Instant::now());
+            hid.encode(report)
+        });
+        // Send the report.
+        match self.writer.write(encoded.as_bytes()).await {
+            Ok(()) => {}
+            Err(e) => warn!("Failed to send report: {:?}", e),
+        };
+    }
+
+    /// Sends every waiting report. Each one goes out on its own poll of the
+    /// interrupt endpoint, which is what paces the typing.
+    async fn flush(&mut self) {
+        while let Some(report) = self.queue.pop() {
+            debug!("Sending {}", report);
+            self.write_report(report).await;
+        }
+    }
+
+    /// Presses each key in a sequence, leaving the last keys down so that the
+    /// next character can be added to the same report
+    async fn send_keys(&mut self, keys: &KeySequence) {
+        // the queue is empty between calls, and has room for any sequence
+        unwrap!(self.queue.push_keys(keys));
+        self.flush().await;
+    }
+
+    /// Presses a key, leaving it down like [Keyboard::send_keys]
+    async fn send_key(&mut self, stroke: KeyStroke) {
+        self.send_keys(&stroke.into()).await;
+    }
+
+    /// Releases every key and modifier that is still down
+    async fn release_all(&mut self) {
+        unwrap!(self.queue.release_all());
+        self.flush().await;
+    }
+}
+
+/// Performs an action from the user code table, typing any text with the
+/// modifiers that are on
+async fn run_user_action(
+    keyboard: &mut Keyboard,
+    host: &Host,
+    history: &mut TypedHistory,
+    modifiers: &mut Modifiers,
+    leds: HostLeds,
+    action: UserAction<'_>,
+) {
+    match action {
+        UserAction::Text(text) => {
+            let step = MacroStep::Text(text);
+            run_macro_step(keyboard, host, history, modifiers, leds, step).await;
+        }
+        UserAction::Key(key) => {
+            let step = MacroStep::Key(key);
+            run_macro_step(keyboard, host, history, modifiers, leds, step).await;
+        }
+        UserAction::Macro(steps) => {
+            for step in steps {
+                run_macro_step(keyboard, host, history, modifiers, leds, step).await;
+            }
+        }
+    }
+}
+
+/// Performs a single step of a user code table action. Key combinations are
+/// pressed exactly as they are in the table.
+async fn run_macro_step(
+    keyboard: &mut Keyboard,
+    host: &Host,
+    history: &mut TypedHistory,
+    modifiers: &mut Modifiers,
+    leds: HostLeds,
+    step: MacroStep<'_>,
+) {
+    match step {
+        MacroStep::Text(text) => {
+            for c in text.chars() {
+                let modifier = modifiers.take();
+                type_char(keyboard, host, history, c, modifier, leds.caps_lock()).await;
+            }
+        }
+        MacroStep::Key(key) => {
+            info!("Pressing {}", key);
+            keyboard.send_key(key).await;
+            // a key combination could move the cursor or change the text, so
+            // backspaces can no longer be trusted to delete what was typed
+            history.clear();
+        }
+        MacroStep::Delay(ms) => {
+            // let go of the keys first, or the host would repeat them
+            keyboard.release_all().await;
+            Timer::after(Duration::from_millis(ms.into())).await;
+        }
+    }
+}
+
+/// Types a single character on the host with the given modifiers, and
+/// remembers it in the history. With just shift the character that shift gives
+/// on the host's layout is typed, e.g. `!` for `1`, with letters in upper case
+/// so they come out right on any layout. Letters are typed in upper case with
+/// the host's Caps Lock on too. With any other modifier the character is sent
+/// as a shortcut instead.
+async fn type_char(
+    keyboard: &mut Keyboard,
+    host: &Host,
+    history: &mut TypedHistory,
+    c: char,
+    modifier: u8,
+    caps_lock: bool,
+) {
+    if modifier & !SHIFT != 0 {
+        let Some(mut keys) = host.keys_for(c) else {
+            warn!("Unable to type '{}' on {}", c, host);
+            return;
+        };
+        info!("Sending '{}' with modifiers {=u8:#x}", c, modifier);
+        keys.hold(modifier);
+        keyboard.send_keys(&keys).await;
+        // like a key combination from the user code table, the shortcut could
+        // have changed anything
+        history.clear();
+        return;
+    }
+
+    let c = if modifier == SHIFT {
+        host.shifted(c)
+    } else if caps_lock {
+        c.to_uppercase().next().unwrap_or(c)
+    } else {
+        c
+    };
+    let Some(keys) = host.keys_for_caps_lock(c, caps_lock) else {
+        warn!("Unable to type '{}' on {}", c, host);
+        return;
+    };
+    info!("Typing '{}'", c);
+    keyboard.send_keys(&keys).await;
+    history.push(c);
+}
+
+/// Presses Caps Lock if the host's Caps Lock doesn't match whether shift is
+/// locked
+async fn sync_caps_lock(keyboard: &mut Keyboard, modifiers: &Modifiers, leds: &mut HostLeds) {
+    if modifiers.caps_lock() != leds.caps_lock() {
+        info!("Pressing Caps Lock");
+        // press it on its own, as hosts can ignore it while other keys are down
+        keyboard.release_all().await;
+        keyboard
+            .send_key(KeyStroke::new(0, ControlKey::CapsLock.hid_code()))
+            .await;
+        keyboard.release_all().await;
+        // the host will send its new LEDs, but until then assume it worked
+        leds.toggle_caps_lock();
+    }
+}
+
+/// Takes the events from the morse code parser out of the event buffer and
+/// sends them on as key presses on the HID keyboard interface, using the host's
+/// keyboard layout and Unicode entry mode. The modifiers picked with prosigns
+/// or the shift button are held down for each character while they are on,
+/// except for locked shift, which is kept in step with the host's Caps Lock and
+/// shown on the onboard LED.
+/// Everything that is typed is tracked so that corrections send the right
+/// number of backspaces.
 #[embassy_executor::task]
 async fn usb_hid_loop(
-    event_receiver: EventReceiver,
-    mut writer: HidWriter<'static, Driver<'static, USB>, 8>,
+    writer: KeyboardWriter,
+    prosign_keys: ProsignKeyMap,
+    host: Host,
+    mut caps_lock_led: Output<'static>,
 ) {
     info!("Starting event loop");
-    // throttle the loop a little bit
-    let mut ticker = Ticker::every(Duration::from_millis(20));
+    let mut history = TypedHistory::default();
+    let mut modifiers = Modifiers::default();
+    let mut leds = HostLeds::default();
+    let mut keyboard = Keyboard::new(writer);
+    let mut overflows = 0;
+
     loop {
-        match event_receiver.try_receive() {
-            Ok((char, shift_held)) => {
-                let Some(code) = char_to_hid_u8(char) else {
+        let dropped = EVENTS.lock(|events| events.borrow().overflows());
+        if dropped != overflows {
+            warn!(
+                "Dropped {} events while the host was busy ({} in total)",
+                dropped.wrapping_sub(overflows),
+                dropped
+            );
+            overflows = dropped;
+        }
+
+        // repeat the last report at the idle rate the host asked for
+        let repeat = HID_STATE.lock(|hid| hid.borrow_mut().repeat(Instant::now()));
+        if let Some(report) = repeat {
+            keyboard.write_report(report).await;
+        }
+
+        if let Some(new_leds) = HOST_LEDS.try_take() {
+            info!("The host's LEDs are {}", new_leds);
+            leds = new_leds;
+            modifiers.sync_caps_lock(leds.caps_lock());
+            caps_lock_led.set_level(Level::from(leds.caps_lock()));
+        }
+
+        // handle everything that is waiting, so text is typed without a pause
+        // between characters
+        while let Some(event) = EVENTS.lock(|events| events.borrow_mut().pop()) {
+            // the modifiers for the action, if it is typed with them, and
+            // shift if the shift button was held
+            let (action, with_modifiers, held) = match event {
+                HidEvent::Decoded(decoded, shift_held) => {
+                    if decoded == Decoded::Char(' ') {
+                        // Caps Word ends with the word, but a one-shot
+                        // modifier still applies to the space
+                        modifiers.end_word();
+                    }
+                    let held = if shift_held { SHIFT } else { 0 };
+                    match decoded_to_action(decoded, &prosign_keys) {
+                        Some(action) => (action, true, held),
+                        None => continue,
+                    }
+                }
+                HidEvent::Action(action) => (action, false, 0),
+                HidEvent::User(code) => {
+                    let table = USER_TABLE.lock().await;
+                    match UserTable::parse(&*table).map(|table| table.lookup(&code)) {
+                        Ok(Some(action)) => {
+                            run_user_action(
+                                &mut keyboard,
+                                &host,
+                                &mut history,
+                                &mut modifiers,
+                                leds,
+                                action,
+                            )
+                            .await;
+                        }
+                        _ => warn!("No user code table entry for {}", code),
+                    }
+                    continue;
+                }
+                HidEvent::ShiftGesture(gesture) => {
+                    modifiers.shift_gesture(gesture);
+                    info!("Shift is {}", modifiers.mode(Modifier::Shift));
+                    sync_caps_lock(&mut keyboard, &modifiers, &mut leds).await;
+                    continue;
+                }
+                HidEvent::WordGap => {
+                    // a modifier picked after the word is kept for the next one
+                    modifiers.end_word();
+                    let caps_lock = leds.caps_lock();
+                    type_char(&mut keyboard, &host, &mut history, ' ', 0, caps_lock).await;
                     continue;
-                };
-
-                info!(
-                    "Sending '{}{}' Key ({}u8)",
-                    if shift_held { "shift+" } else { "" },
-                    char,
-                    code
-                );
-                let report = KeyboardReport {
-                    keycodes: [code, 0, 0, 0, 0, 0],
-                    leds: 0,
-                    modifier: if shift_held { 0x02 } else { 0 },
-                    reserved: 0,
-                };
-                // Send the report.
-                match writer.write_serialize(&report).await {
-                    Ok(()) => {}
-                    Err(e) => warn!("Failed to send report: {:?}", e),
-                };
-
-                // delay 10ms before we release the key
-                Timer::after(Duration::from_millis(10)).await;
-
-                info!("Releasing '{}' Key", char);
-                let report = KeyboardReport {
-                    keycodes: [0, 0, 0, 0, 0, 0],
-                    leds: 0,
-                    modifier: 0,
-                    reserved: 0,
-                };
-                // Send the report.
-                match writer.write_serialize(&report).await {
-                    Ok(()) => {}
-                    Err(e) => warn!("Failed to send report: {:?}", e),
-                };
+                }
+            };
+
+            match action {
+                KeyAction::Type(c) => {
+                    let modifier = if with_modifiers {
+                        modifiers.take() | held
+                    } else {
+                        0
+                    };
+                    let caps_lock = leds.caps_lock();
+                    type_char(&mut keyboard, &host, &mut history, c, modifier, caps_lock).await;
+                }
+                KeyAction::TypeText(text) => {
+                    // a one-shot modifier only applies to the first character
+                    for c in text.chars() {
+                        let modifier = if with_modifiers {
+                            modifiers.take() | held
+                        } else {
+                            0
+                        };
+                        let caps_lock = leds.caps_lock();
+                        type_char(&mut keyboard, &host, &mut history, c, modifier, caps_lock).await;
+                    }
+                }
+                KeyAction::Control(key) => {
+                    info!("Pressing {}", key);
+                    let modifier = if with_modifiers {
+                        modifiers.take() | held
+                    } else {
+                        0
+                    };
+                    keyboard
+                        .send_key(KeyStroke::new(modifier, key.hid_code()))
+                        .await;
+                    if modifier & !SHIFT == 0 {
+                        history.press(key);
+                    } else {
+                        history.clear();
+                    }
+                }
+                KeyAction::Modifier(modifier) => {
+                    let mode = modifiers.pick(modifier);
+                    info!("{} is {}", modifier, mode);
+                    sync_caps_lock(&mut keyboard, &modifiers, &mut leds).await;
+                }
+                KeyAction::WordModifiers => {
+                    info!("Keeping the modifiers on until the end of the word");
+                    modifiers.extend_to_word();
+                }
+                KeyAction::DeleteCharacter => {
+                    let count = history.delete_character();
+                    info!("Deleting the last character");
+                    for _ in 0..count {
+                        keyboard
+                            .send_key(KeyStroke::new(0, ControlKey::Backspace.hid_code()))
+                            .await;
+                    }
+                }
+                KeyAction::DeleteWord => {
+                    let count = history.delete_word();
+                    info!("Deleting the last word ({} characters)", count);
+                    for _ in 0..count {
+                        keyboard
+                            .send_key(KeyStroke::new(0, ControlKey::Backspace.hid_code()))
+                            .await;
+                    }
+                }
             }
-            Err(_err) => {
-                // nop - we just move on
+        }
+        // then let go of the keys, before the host starts repeating them
+        keyboard.release_all().await;
+
+        // wait for something to do, or until the last report is due again
+        match HID_STATE.lock(|hid| hid.borrow().next_repeat()) {
+            Some(at) => {
+                select(HID_WAKE.wait(), Timer::at(at)).await;
             }
+            None => HID_WAKE.wait().await,
         }
+    }
+}
 
-        ticker.next().await;
+/// Passes the keyboard LEDs from output reports on the OUT endpoint on. They
+/// can also be set with SET_REPORT, which [KodeboardUsbRequestHandler] handles.
+#[embassy_executor::task]
+async fn read_output_reports(mut reader: KeyboardReader) {
+    let mut report = [0; 64];
+    loop {
+        match reader.read(&mut report).await {
+            Ok(len) => match HostLeds::from_report(&report[..len]) {
+                Some(leds) => {
+                    HOST_LEDS.signal(leds);
+                    HID_WAKE.signal(());
+                }
+                None => warn!("Unexpected output report: {=[u8]}", &report[..len]),
+            },
+            Err(EndpointError::BufferOverflow) => warn!("The output report is too long"),
+            Err(EndpointError::Disabled) => reader.wait_enabled().await,
+        }
+    }
+}
+
+/// Starts using a user code table if it is valid
+async fn load_user_table(bytes: &[u8; MAX_TABLE_LEN]) {
+    match UserTable::parse(bytes) {
+        Ok(table) => {
+            info!("Loaded a user code table with {} entries", table.len());
+            USER_CODES.signal(table.codes());
+            *USER_TABLE.lock().await = *bytes;
+        }
+        Err(TableError::BadHeader) => info!("No user code table has been stored"),
+        Err(e) => warn!("Unable to load the user code table: {}", e),
     }
 }
 
-/// Handles USB requests received on the [`HidReader`]
+/// Stores user code tables that were uploaded over USB in flash, and then
+/// starts using them.
+///
+/// The code runs from flash, so nothing else can run while it is erased and
+/// written, which takes tens of milliseconds. The other tasks can't be moved
+/// out of the way, so instead the sampling task is told that a table is being
+/// stored and throws away whatever was being sent once it is done.
 #[embassy_executor::task]
-async fn usb_request_handler(reader: HidReader<'static, Driver<'static, USB>, 1>) {
-    let mut request_handler = usb::KodeboardUsbRequestHandler::default();
-    reader.run(false, &mut request_handler).await;
+async fn store_user_tables(mut flash: Flash<'static, FLASH, Blocking, FLASH_SIZE>) {
+    loop {
+        let table = USER_TABLE_UPLOADS.wait().await;
+        info!("Storing the new user code table");
+        STORING_USER_TABLE.store(true, Ordering::Relaxed);
+        // give the sampling task a chance to see that the buttons are about
+        // to stop being read
+        Timer::after(Duration::from_millis(2)).await;
+        let stored = flash
+            .blocking_erase(USER_TABLE_OFFSET, USER_TABLE_OFFSET + ERASE_SIZE as u32)
+            .and_then(|()| flash.blocking_write(USER_TABLE_OFFSET, &table));
+        STORING_USER_TABLE.store(false, Ordering::Relaxed);
+        if let Err(e) = stored {
+            // the table is still used, it just won't survive a restart
+            warn!("Unable to store the user code table: {}", e);
+        }
+        load_user_table(&table).await;
+    }
 }
 
 /// Listens for the space key and then sends a "space" event to the keyboard
 #[embassy_executor::task]
-async fn monitor_space_key(space_btn: &'static ButtonType, sender: EventSender) {
+async fn monitor_space_key(space_btn: &'static ButtonType) {
     let mut ticker = Ticker::every(Duration::from_millis(5));
     let mut btn_debouncer = if let Some(btn_ref) = space_btn.lock().await.as_ref() {
         debouncer::DebouncedInput::new(btn_ref.is_high())
@@ -243,7 +757,7 @@ async fn monitor_space_key(space_btn: &'static ButtonType, sender: EventSender)
 
             if result {
                 info!("Space button pressed");
-                sender.send((' ', false)).await;
+                send_event(HidEvent::Decoded(Decoded::Char(' '), false));
             }
         }
 
@@ -251,17 +765,114 @@ async fn monitor_space_key(space_btn: &'static ButtonType, sender: EventSender)
     }
 }
 
+/// Updates the morse decoder with the state of the key, and sends any decoded
+/// characters and spaces on to the USB HID task. Anything sent while the shift
+/// button is held is shifted, except for the letters that are chords with it,
+/// see [Chord], and prosigns, which type their character instead.
+fn push_to_decoder(
+    morse_decoder: &mut decoder::Decoder<Instant>,
+    key_down: bool,
+    time: Instant,
+    shift: &mut ShiftButton<Instant>,
+) {
+    for event in morse_decoder.push(key_down, time) {
+        let decoded = match event {
+            DecoderEvent::Element { .. } => continue,
+            DecoderEvent::Character(c) if shift.is_held() => match Chord::for_letter(c) {
+                Some(chord) => {
+                    // this press of the shift button is now a chord, not a tap
+                    shift.chord();
+                    let action = match chord {
+                        Chord::Pick(modifier) => KeyAction::Modifier(modifier),
+                        Chord::Word => KeyAction::WordModifiers,
+                    };
+                    send_event(HidEvent::Action(action));
+                    continue;
+                }
+                None => Decoded::Char(c),
+            },
+            DecoderEvent::Character(c) => Decoded::Char(c),
+            DecoderEvent::Text(text) => Decoded::Text(text),
+            DecoderEvent::Prosign(prosign) => match shifted_prosign_action(prosign) {
+                Some(action) if shift.is_held() => {
+                    shift.chord();
+                    send_event(HidEvent::Action(action));
+                    continue;
+                }
+                _ => Decoded::Prosign(prosign),
+            },
+            DecoderEvent::WordGap => {
+                // the word may have ended after the shift button was pressed,
+                // which doesn't make the press a chord
+                send_event(HidEvent::WordGap);
+                continue;
+            }
+            DecoderEvent::ShortError => {
+                send_event(HidEvent::Action(KeyAction::DeleteCharacter));
+                continue;
+            }
+            DecoderEvent::Error(e) => {
+                warn!("Unable to decode morse sequence: {}", e);
+                continue;
+            }
+            DecoderEvent::UserCode(code) => {
+                send_event(HidEvent::User(code));
+                continue;
+            }
+            DecoderEvent::Pattern(code) => {
+                for element in code.elements() {
+                    let c = element.as_char();
+                    send_event(HidEvent::Action(KeyAction::Type(c)));
+                }
+                continue;
+            }
+        };
+        // anything sent while the shift button is held is shifted, and means
+        // the press wasn't a tap
+        let shift_held = shift.is_held();
+        shift.chord();
+        send_event(HidEvent::Decoded(decoded, shift_held));
+    }
+}
+
 /// Listens to the supplied button and passes button actions (press/release) to
 /// a morse code decoder. As characters are received by the encoder it sends them
-/// through the [`EventSender`] channel for transmission via USB HID.
+/// on for transmission via USB HID.
+///
+/// Nothing here waits for the USB HID task, so the buttons are always sampled
+/// and timestamped on time.
+///
+/// In paddle mode the morse button is the dit paddle and the dah button is the
+/// dah paddle, and they are passed through a keyer before the decoder.
 #[embassy_executor::task]
 async fn generate_morse_code_characters(
     morse_btn: &'static ButtonType,
+    dah_btn: &'static ButtonType,
     shift_btn: &'static ButtonType,
-    sender: EventSender,
 ) {
     info!("Configuring morse decoder");
-    let mut morse_decoder = decoder::Decoder::new(60);
+    let (timing, mut keyer) = match INPUT_MODE {
+        InputMode::StraightKey => {
+            let timing = match FARNSWORTH {
+                Some((character_wpm, effective_wpm)) => {
+                    decoder::Timing::farnsworth(character_wpm, effective_wpm)
+                }
+                // Use `Timing::new` for a fixed speed, and widen the gaps here
+                // for slower operators
+                None => decoder::Timing::adaptive(60),
+            };
+            (timing, None)
+        }
+        // The keyer sends perfectly timed elements so the speed is fixed
+        InputMode::Paddles(config) => (
+            decoder::Timing::new(config.dit_ms),
+            Some(keyer::Keyer::new(config)),
+        ),
+    };
+    let mut morse_decoder = decoder::Decoder::new(timing, Instant::now());
+    morse_decoder.error_policy = ERROR_POLICY;
+    morse_decoder.set_table(CODE_TABLE);
+    morse_decoder.cut_numbers_prosign = CUT_NUMBERS_PROSIGN;
     let mut ticker = Ticker::every(Duration::from_millis(1));
 
     let mut morse_debouncer = if let Some(btn_ref) = morse_btn.lock().await.as_ref() {
@@ -270,16 +881,42 @@ async fn generate_morse_code_characters(
         crate::panic!("Unable to configure morse button")
     };
 
+    let mut dah_debouncer = if let Some(btn_ref) = dah_btn.lock().await.as_ref() {
+        debouncer::DebouncedInput::new(btn_ref.is_high())
+    } else {
+        crate::panic!("Unable to configure dah button")
+    };
+
     let mut shift_debouncer = if let Some(btn_ref) = shift_btn.lock().await.as_ref() {
         debouncer::DebouncedInput::new(btn_ref.is_high())
     } else {
         crate::panic!("Unable to configure shift button")
     };
-    let mut prev_shift_state = shift_debouncer.current();
-    let mut shift_held = false;
+    let mut shift = ShiftButton::default();
+    let mut paused = false;
 
     info!("Starting morse listen loop");
     loop {
+        // the buttons can't be read while a user code table is stored, so
+        // what was being sent then can't be decoded
+        if STORING_USER_TABLE.load(Ordering::Relaxed) {
+            paused = true;
+            ticker.next().await;
+            continue;
+        } else if paused {
+            info!("Resetting the decoder after storing a user code table");
+            paused = false;
+            morse_decoder.reset(Instant::now());
+            if let InputMode::Paddles(config) = INPUT_MODE {
+                keyer = Some(keyer::Keyer::new(config));
+            }
+        }
+
+        if let Some(codes) = USER_CODES.try_take() {
+            info!("Using {} user codes", codes.as_slice().len());
+            morse_decoder.set_user_codes(codes);
+        }
+
         // debounce the input
         let morse_btn = if let Some(btn) = read_button!(morse_btn) {
             morse_debouncer.debounce(btn)
@@ -292,20 +929,36 @@ async fn generate_morse_code_characters(
         } else {
             shift_debouncer.current()
         };
+        let change_time = Instant::now();
 
-        if shift_button != prev_shift_state {
-            prev_shift_state = shift_button;
-            if shift_button {
-                shift_held = !shift_held;
-                info!("Toggled Shift to {}", shift_held);
+        if let Some(gesture) = shift.update(shift_button, change_time) {
+            if gesture == Gesture::DoubleTap {
+                // a double tap only toggles cut numbers, the modifiers undo
+                // what the first tap did to shift
+                let cut_numbers = morse_decoder.toggle_cut_numbers();
+                info!("Toggled cut numbers to {}", cut_numbers);
             }
+            send_event(HidEvent::ShiftGesture(gesture));
         }
 
-        // update the morse decoder
-        let change_time = Instant::now();
-        if let Some(char) = morse_decoder.push(morse_btn, change_time) {
-            sender.send((char, shift_held)).await;
-        }
+        // update the morse decoder, either directly or through the keyer
+        let key_down = if let Some(keyer) = keyer.as_mut() {
+            let dah_btn = if let Some(btn) = read_button!(dah_btn) {
+                dah_debouncer.debounce(btn)
+            } else {
+                dah_debouncer.current()
+            };
+
+            // the keyer reports exactly when the key changed, which may be
+            // slightly before now
+            if let Some(edge) = keyer.update(change_time, morse_btn, dah_btn) {
+                push_to_decoder(&mut morse_decoder, edge.key_down, edge.at, &mut shift);
+            }
+            keyer.is_key_down()
+        } else {
+            morse_btn
+        };
+        push_to_decoder(&mut morse_decoder, key_down, change_time, &mut shift);
 
         // only check inputs periodically
         ticker.next().await;
@@ -1,47 +1,297 @@
+use core::cell::RefCell;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use defmt::*;
-use embassy_usb::Handler;
-use embassy_usb::class::hid::{ReportId, RequestHandler};
-use embassy_usb::control::OutResponse;
+use embassy_sync::blocking_mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use embassy_usb::control::{InResponse, OutResponse, Recipient, Request, RequestType};
+use embassy_usb::driver::Driver;
+use embassy_usb::types::InterfaceNumber;
+use embassy_usb::{Builder, Handler};
+use kodeboard_core::hid::{HidState, Protocol};
+use kodeboard_core::host::HostLeds;
+use kodeboard_core::user_table::{MAX_TABLE_LEN, TableError, UserTable};
 use {defmt_rtt as _, panic_probe as _};
 
-#[derive(Default)]
-pub struct KodeboardUsbRequestHandler {}
+/// Vendor request that writes part of a new user code table, starting at the
+/// offset in `wValue`. Tables are sent in pieces as each request only carries
+/// one control buffer of data.
+const WRITE_USER_TABLE: u8 = 0x10;
+/// Vendor request that checks the new user code table and stores it if it is
+/// valid, or stalls if it isn't
+const COMMIT_USER_TABLE: u8 = 0x11;
+/// Vendor request that reads back whether the last table was accepted, see
+/// [status_reply]
+const USER_TABLE_STATUS: u8 = 0x12;
 
-impl RequestHandler for KodeboardUsbRequestHandler {
-    fn get_report(&mut self, id: ReportId, _buf: &mut [u8]) -> Option<usize> {
-        info!("Get report for {:?}", id);
-        None
+/// The HID class, with the boot interface subclass and keyboard protocol so
+/// that BIOS setup screens and bootloaders find the keyboard
+const USB_CLASS_HID: u8 = 0x03;
+const HID_SUBCLASS_BOOT: u8 = 0x01;
+const HID_PROTOCOL_KEYBOARD: u8 = 0x01;
+
+// HID descriptor types
+const HID_DESCRIPTOR: u8 = 0x21;
+const HID_REPORT_DESCRIPTOR: u8 = 0x22;
+
+// HID class requests
+const HID_GET_REPORT: u8 = 0x01;
+const HID_GET_IDLE: u8 = 0x02;
+const HID_GET_PROTOCOL: u8 = 0x03;
+const HID_SET_REPORT: u8 = 0x09;
+const HID_SET_IDLE: u8 = 0x0a;
+const HID_SET_PROTOCOL: u8 = 0x0b;
+
+// The report types in the upper byte of GET_REPORT and SET_REPORT
+const HID_REPORT_INPUT: u8 = 0x01;
+const HID_REPORT_OUTPUT: u8 = 0x02;
+
+/// The largest packet on the keyboard's interrupt endpoints
+const HID_MAX_PACKET_SIZE: u16 = 64;
+
+/// Passes user code tables that have been uploaded and checked on to be stored
+pub type UserTableSignal = Signal<ThreadModeRawMutex, [u8; MAX_TABLE_LEN]>;
+
+/// Passes the keyboard LEDs on whenever the host changes them
+pub type HostLedsSignal = Signal<ThreadModeRawMutex, HostLeds>;
+
+/// The state of the HID interface, shared by the requests from the host and
+/// the task that sends reports
+pub type HidStateMutex = blocking_mutex::Mutex<ThreadModeRawMutex, RefCell<HidState<Instant>>>;
+
+/// Wakes the task that sends reports when there is something new for it to do
+pub type WakeSignal = Signal<ThreadModeRawMutex, ()>;
+
+/// The reply to [USER_TABLE_STATUS]: zero if the last table was accepted, or
+/// the reason it was rejected followed by the entries that were wrong
+fn status_reply(status: Result<(), TableError>) -> [u8; 3] {
+    match status {
+        Ok(()) => [0, 0, 0],
+        Err(TableError::BadHeader) => [1, 0, 0],
+        Err(TableError::TooBig) => [2, 0, 0],
+        Err(TableError::Truncated) => [3, 0, 0],
+        Err(TableError::BadChecksum) => [4, 0, 0],
+        Err(TableError::Malformed(entry)) => [5, entry as u8, 0],
+        Err(TableError::DuplicateCode { first, second }) => [6, first as u8, second as u8],
+        Err(TableError::PrefixConflict { prefix, code }) => [7, prefix as u8, code as u8],
+    }
+}
+
+/// Handles the requests for the HID keyboard interface on the control
+/// endpoint. This is done here rather than with embassy's HID class so that
+/// the keyboard can be a boot keyboard and switch protocol.
+pub struct KodeboardUsbRequestHandler {
+    /// The keyboard interface, which is known once it has been added
+    interface: Option<InterfaceNumber>,
+    report_descriptor: &'static [u8],
+    /// The class descriptor that points the host at the report descriptor
+    hid_descriptor: [u8; 9],
+    /// Where to send the keyboard LEDs from output reports
+    leds: &'static HostLedsSignal,
+    /// The protocol, idle rate and last report
+    hid: &'static HidStateMutex,
+    /// Wakes the task that sends reports when the LEDs or idle rate change
+    wake: &'static WakeSignal,
+}
+
+impl KodeboardUsbRequestHandler {
+    pub fn new(
+        report_descriptor: &'static [u8],
+        leds: &'static HostLedsSignal,
+        hid: &'static HidStateMutex,
+        wake: &'static WakeSignal,
+    ) -> Self {
+        let [len_low, len_high] = (report_descriptor.len() as u16).to_le_bytes();
+        KodeboardUsbRequestHandler {
+            interface: None,
+            report_descriptor,
+            hid_descriptor: [
+                // length
+                9,
+                HID_DESCRIPTOR,
+                // HID 1.11
+                0x11,
+                0x01,
+                // not localised
+                0,
+                // one report descriptor follows
+                1,
+                HID_REPORT_DESCRIPTOR,
+                len_low,
+                len_high,
+            ],
+            leds,
+            hid,
+            wake,
+        }
+    }
+
+    /// Adds the keyboard interface to the device, returning its IN endpoint for
+    /// input reports and OUT endpoint for the LEDs
+    pub fn add_interface<'d, D: Driver<'d>>(
+        &'d mut self,
+        builder: &mut Builder<'d, D>,
+        poll_ms: u8,
+    ) -> (D::EndpointIn, D::EndpointOut) {
+        let mut function =
+            builder.function(USB_CLASS_HID, HID_SUBCLASS_BOOT, HID_PROTOCOL_KEYBOARD);
+        let mut interface = function.interface();
+        self.interface = Some(interface.interface_number());
+        let mut alt = interface.alt_setting(
+            USB_CLASS_HID,
+            HID_SUBCLASS_BOOT,
+            HID_PROTOCOL_KEYBOARD,
+            None,
+        );
+        // the descriptor includes its length and type here
+        alt.descriptor(HID_DESCRIPTOR, &self.hid_descriptor[2..]);
+        let endpoints = (
+            alt.endpoint_interrupt_in(HID_MAX_PACKET_SIZE, poll_ms),
+            alt.endpoint_interrupt_out(HID_MAX_PACKET_SIZE, poll_ms),
+        );
+        drop(function);
+        builder.handler(self);
+        endpoints
+    }
+
+    /// Whether the request is for the keyboard interface
+    fn is_for_interface(&self, req: &Request, request_type: RequestType) -> bool {
+        req.request_type == request_type
+            && req.recipient == Recipient::Interface
+            && self
+                .interface
+                .is_some_and(|interface| req.index == u16::from(interface.0))
     }
 
-    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
-        info!("Set report for {:?}: {=[u8]}", id, data);
-        OutResponse::Accepted
+    /// Passes the LEDs from an output report on, returning whether the report
+    /// was valid
+    fn set_leds(&self, report: &[u8]) -> bool {
+        let Some(leds) = HostLeds::from_report(report) else {
+            return false;
+        };
+        self.leds.signal(leds);
+        self.wake.signal(());
+        true
     }
+}
+
+impl Handler for KodeboardUsbRequestHandler {
+    fn control_out(&mut self, req: Request, data: &[u8]) -> Option<OutResponse> {
+        if !self.is_for_interface(&req, RequestType::Class) {
+            return None;
+        }
 
-    fn set_idle_ms(&mut self, id: Option<ReportId>, dur: u32) {
-        info!("Set idle rate for {:?} to {:?}", id, dur);
+        let [_, value_high] = req.value.to_le_bytes();
+        match req.request {
+            // the keyboard's only output report is its LEDs
+            HID_SET_REPORT if value_high == HID_REPORT_OUTPUT => {
+                info!("Set report: {=[u8]}", data);
+                if self.set_leds(data) {
+                    Some(OutResponse::Accepted)
+                } else {
+                    Some(OutResponse::Rejected)
+                }
+            }
+            HID_SET_IDLE => {
+                // there is only one input report, so it doesn't matter which
+                // it is for
+                info!("Set idle rate to {}", value_high);
+                self.hid.lock(|hid| hid.borrow_mut().set_idle(value_high));
+                self.wake.signal(());
+                Some(OutResponse::Accepted)
+            }
+            HID_SET_PROTOCOL => {
+                let protocol = match req.value {
+                    0 => Protocol::Boot,
+                    1 => Protocol::Report,
+                    _ => return Some(OutResponse::Rejected),
+                };
+                info!("Set protocol to {}", protocol);
+                // reports are encoded for the protocol as they are sent
+                self.hid.lock(|hid| hid.borrow_mut().set_protocol(protocol));
+                Some(OutResponse::Accepted)
+            }
+            _ => Some(OutResponse::Rejected),
+        }
     }
 
-    fn get_idle_ms(&mut self, id: Option<ReportId>) -> Option<u32> {
-        info!("Get idle rate for {:?}", id);
-        None
+    fn control_in<'a>(&'a mut self, req: Request, buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        let [_, value_high] = req.value.to_le_bytes();
+        if self.is_for_interface(&req, RequestType::Standard) {
+            return match (req.request, value_high) {
+                (Request::GET_DESCRIPTOR, HID_REPORT_DESCRIPTOR) => {
+                    Some(InResponse::Accepted(self.report_descriptor))
+                }
+                (Request::GET_DESCRIPTOR, HID_DESCRIPTOR) => {
+                    Some(InResponse::Accepted(&self.hid_descriptor))
+                }
+                _ => None,
+            };
+        }
+        if !self.is_for_interface(&req, RequestType::Class) {
+            return None;
+        }
+
+        match req.request {
+            HID_GET_REPORT if value_high == HID_REPORT_INPUT => {
+                info!("Get report");
+                let report = self.hid.lock(|hid| {
+                    let hid = hid.borrow();
+                    hid.encode(hid.report())
+                });
+                let report = report.as_bytes();
+                let reply = buf.get_mut(..report.len())?;
+                reply.copy_from_slice(report);
+                Some(InResponse::Accepted(reply))
+            }
+            HID_GET_IDLE => {
+                info!("Get idle rate");
+                buf[0] = self.hid.lock(|hid| hid.borrow().idle());
+                Some(InResponse::Accepted(&buf[..1]))
+            }
+            HID_GET_PROTOCOL => {
+                buf[0] = match self.hid.lock(|hid| hid.borrow().protocol()) {
+                    Protocol::Boot => 0,
+                    Protocol::Report => 1,
+                };
+                Some(InResponse::Accepted(&buf[..1]))
+            }
+            _ => Some(InResponse::Rejected),
+        }
     }
 }
 
 pub struct KodeboardUsbDeviceHandler {
     configured: AtomicBool,
+    /// The user code table being uploaded
+    upload: [u8; MAX_TABLE_LEN],
+    /// Whether the last user code table was accepted
+    status: Result<(), TableError>,
+    /// Where to send user code tables once they have been checked
+    user_tables: &'static UserTableSignal,
+    /// The HID interface state, which a bus reset puts back to the defaults
+    hid: &'static HidStateMutex,
 }
 
-impl Default for KodeboardUsbDeviceHandler {
-    fn default() -> Self {
+impl KodeboardUsbDeviceHandler {
+    pub fn new(user_tables: &'static UserTableSignal, hid: &'static HidStateMutex) -> Self {
         KodeboardUsbDeviceHandler {
             configured: AtomicBool::new(false),
+            upload: [0; MAX_TABLE_LEN],
+            status: Ok(()),
+            user_tables,
+            hid,
         }
     }
 }
 
+/// Whether the request is one of ours rather than a standard request
+fn is_vendor_request(req: &Request) -> bool {
+    req.request_type == RequestType::Vendor && req.recipient == Recipient::Device
+}
+
 impl Handler for KodeboardUsbDeviceHandler {
     fn enabled(&mut self, enabled: bool) {
         self.configured.store(false, Ordering::Relaxed);
@@ -54,6 +304,7 @@ impl Handler for KodeboardUsbDeviceHandler {
 
     fn reset(&mut self) {
         self.configured.store(false, Ordering::Relaxed);
+        self.hid.lock(|hid| hid.borrow_mut().reset());
         info!("Bus reset, the Vbus current limit is 100mA");
     }
 
@@ -72,4 +323,49 @@ impl Handler for KodeboardUsbDeviceHandler {
             info!("Device is no longer configured, the Vbus current limit is 100mA.");
         }
     }
+
+    fn control_out(&mut self, req: Request, data: &[u8]) -> Option<OutResponse> {
+        if !is_vendor_request(&req) {
+            return None;
+        }
+
+        match req.request {
+            WRITE_USER_TABLE => {
+                let start = req.value as usize;
+                let Some(piece) = self.upload.get_mut(start..start + data.len()) else {
+                    warn!("User code table upload is too long");
+                    self.status = Err(TableError::TooBig);
+                    return Some(OutResponse::Rejected);
+                };
+                piece.copy_from_slice(data);
+                Some(OutResponse::Accepted)
+            }
+            COMMIT_USER_TABLE => {
+                self.status = UserTable::parse(&self.upload).map(|table| {
+                    info!("Received a user code table with {} entries", table.len());
+                });
+                match self.status {
+                    Ok(()) => {
+                        self.user_tables.signal(self.upload);
+                        Some(OutResponse::Accepted)
+                    }
+                    Err(e) => {
+                        warn!("Rejected user code table: {}", e);
+                        Some(OutResponse::Rejected)
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn control_in<'a>(&'a mut self, req: Request, buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        if !is_vendor_request(&req) || req.request != USER_TABLE_STATUS {
+            return None;
+        }
+
+        let reply = status_reply(self.status);
+        buf[..reply.len()].copy_from_slice(&reply);
+        Some(InResponse::Accepted(&buf[..reply.len()]))
+    }
 }